@@ -1,50 +1,199 @@
+use world::Rectangle;
+
+const TRAUMA_DECAY : f64 = 1.5f64;
+const SHAKE_FREQUENCY : f64 = 25f64;
+const MIN_ZOOM : f64 = 0.25f64;
+const MAX_ZOOM : f64 = 8f64;
+
+#[derive(Clone, Copy, Debug)]
+pub enum FollowMode {
+    Snap,
+    // fraction of the distance to the target covered per second
+    Lerp(f64),
+    Spring { stiffness : f64, damping : f64 }
+}
+
+#[derive(Clone)]
 pub struct Camera {
+    // the center of the view in world coordinates, shake included
     pub position : (f64, f64),
-    pub size : (u32, u32),
+    // the part of the logical screen the camera renders to
+    pub viewport : (i32, i32, u32, u32),
+    pub zoom : f64,
+    pub follow_mode : FollowMode,
+    // the target can move this far from the center before the camera follows
+    pub deadzone : (f64, f64),
+    // seconds of target velocity to look ahead
+    pub look_ahead : f64,
+    // offset in world units at full trauma
+    pub max_shake : f64,
     world_size : (u32, u32),
-    min_x : f64,
-    max_x : f64,
-    min_y : f64,
-    max_y : f64
+    target : (f64, f64),
+    focus : (f64, f64),
+    focus_velocity : (f64, f64),
+    trauma : f64,
+    shake_time : f64
 }
 
 impl Camera {
     pub fn new(size : (u32, u32)) -> Camera {
         Camera {
             position : (0f64, 0f64),
-            size : size,
+            viewport : (0, 0, size.0, size.1),
+            zoom : 1f64,
+            follow_mode : FollowMode::Snap,
+            deadzone : (0f64, 0f64),
+            look_ahead : 0f64,
+            max_shake : 4f64,
             world_size : size,
-            min_x : 0f64,
-            max_x : 0f64,
-            min_y : 0f64,
-            max_y : 0f64
+            target : (0f64, 0f64),
+            focus : (0f64, 0f64),
+            focus_velocity : (0f64, 0f64),
+            trauma : 0f64,
+            shake_time : 0f64
         }
     }
 
     pub fn set_world_size(&mut self, w : u32, h : u32) {
         self.world_size = (w, h);
-        self.min_x = self.size.0 as f64 / 2f64;
-        self.min_y = self.size.1 as f64 / 2f64;
-        self.max_x = self.world_size.0 as f64 - self.min_x;
-        self.max_y = self.world_size.1 as f64 - self.min_y;
     }
 
+    pub fn set_viewport(&mut self, x : i32, y : i32, w : u32, h : u32) {
+        self.viewport = (x, y, w, h);
+        self.focus = self.clamp(self.focus.0, self.focus.1);
+    }
+
+    // the size of the visible part of the world
+    pub fn get_view_size(&self) -> (f64, f64) {
+        (self.viewport.2 as f64 / self.zoom, self.viewport.3 as f64 / self.zoom)
+    }
+
+    pub fn set_zoom(&mut self, zoom : f64) {
+        self.zoom = zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+    }
+
+    // moves the camera right away, without smoothing
     pub fn set_position(&mut self, x : f64, y : f64) {
-        let mut camera_x = x;
-        let mut camera_y = y;
+        self.target = (x, y);
+        self.focus = self.clamp(x, y);
+        self.focus_velocity = (0f64, 0f64);
+        self.position = self.focus;
+    }
 
-        if camera_x < self.min_x {
-            camera_x = self.min_x;
-        }
-        if camera_y < self.min_y {
-            camera_y = self.min_y;
+    pub fn follow(&mut self, x : f64, y : f64, velocity_x : f64, velocity_y : f64) {
+        self.target = (x + velocity_x * self.look_ahead, y + velocity_y * self.look_ahead);
+    }
+
+    // trauma is between 0 and 1, the shake grows with the square of it
+    pub fn add_trauma(&mut self, amount : f64) {
+        self.trauma = (self.trauma + amount).min(1f64).max(0f64);
+    }
+
+    pub fn update(&mut self, delta_time : f64) {
+        let goal = self.apply_deadzone();
+
+        match self.follow_mode {
+            FollowMode::Snap => {
+                self.focus = goal;
+            },
+            FollowMode::Lerp(speed) => {
+                let t = 1f64 - (1f64 - speed.min(1f64)).powf(delta_time);
+                self.focus.0 += (goal.0 - self.focus.0) * t;
+                self.focus.1 += (goal.1 - self.focus.1) * t;
+            },
+            FollowMode::Spring { stiffness, damping } => {
+                let ax = (goal.0 - self.focus.0) * stiffness - self.focus_velocity.0 * damping;
+                let ay = (goal.1 - self.focus.1) * stiffness - self.focus_velocity.1 * damping;
+                self.focus_velocity.0 += ax * delta_time;
+                self.focus_velocity.1 += ay * delta_time;
+                self.focus.0 += self.focus_velocity.0 * delta_time;
+                self.focus.1 += self.focus_velocity.1 * delta_time;
+            }
         }
-        if camera_x > self.max_x {
-            camera_x = self.max_x;
+        self.focus = self.clamp(self.focus.0, self.focus.1);
+
+        self.shake_time += delta_time;
+        self.trauma = (self.trauma - TRAUMA_DECAY * delta_time).max(0f64);
+        let shake = self.trauma * self.trauma * self.max_shake;
+        let t = self.shake_time * SHAKE_FREQUENCY;
+        // cheap smooth noise, a sum of sines with unrelated frequencies
+        let noise_x = ((t * 1.0f64).sin() + (t * 2.3f64 + 1.7f64).sin()) / 2f64;
+        let noise_y = ((t * 1.3f64 + 4.1f64).sin() + (t * 1.9f64 + 0.3f64).sin()) / 2f64;
+
+        self.position = (self.focus.0 + noise_x * shake, self.focus.1 + noise_y * shake);
+    }
+
+    // the point the camera moves towards, it stays put while the target is inside the deadzone
+    fn apply_deadzone(&self) -> (f64, f64) {
+        let mut goal = self.focus;
+        let half_w = self.deadzone.0 / 2f64;
+        let half_h = self.deadzone.1 / 2f64;
+        if self.target.0 < self.focus.0 - half_w {
+            goal.0 = self.target.0 + half_w;
+        } else if self.target.0 > self.focus.0 + half_w {
+            goal.0 = self.target.0 - half_w;
         }
-        if camera_y > self.max_y {
-            camera_y = self.max_y;
+        if self.target.1 < self.focus.1 - half_h {
+            goal.1 = self.target.1 + half_h;
+        } else if self.target.1 > self.focus.1 + half_h {
+            goal.1 = self.target.1 - half_h;
         }
-        self.position = (camera_x, camera_y);
+        goal
+    }
+
+    // centers the view on worlds smaller than the view
+    fn clamp(&self, x : f64, y : f64) -> (f64, f64) {
+        let (view_w, view_h) = self.get_view_size();
+        (clamp_axis(x, view_w, self.world_size.0 as f64), clamp_axis(y, view_h, self.world_size.1 as f64))
+    }
+
+    // the top left corner of the view in world coordinates
+    pub fn get_offset(&self) -> (f64, f64) {
+        let (view_w, view_h) = self.get_view_size();
+        (self.position.0 - view_w / 2f64, self.position.1 - view_h / 2f64)
+    }
+
+    pub fn world_to_screen(&self, x : f64, y : f64) -> (f64, f64) {
+        let (offset_x, offset_y) = self.get_offset();
+        ((x - offset_x) * self.zoom + self.viewport.0 as f64, (y - offset_y) * self.zoom + self.viewport.1 as f64)
+    }
+
+    pub fn screen_to_world(&self, x : f64, y : f64) -> (f64, f64) {
+        let (offset_x, offset_y) = self.get_offset();
+        ((x - self.viewport.0 as f64) / self.zoom + offset_x, (y - self.viewport.1 as f64) / self.zoom + offset_y)
+    }
+
+    pub fn contains_screen_point(&self, x : i32, y : i32) -> bool {
+        let (vx, vy, vw, vh) = self.viewport;
+        x >= vx && y >= vy && x < vx + vw as i32 && y < vy + vh as i32
+    }
+
+    // the tiles touched by the view as (start_x, start_y, end_x, end_y), the end is exclusive
+    // and the range never leaves the world
+    pub fn visible_tile_range(&self, tile_size : f64) -> (u32, u32, u32, u32) {
+        let (offset_x, offset_y) = self.get_offset();
+        let (view_w, view_h) = self.get_view_size();
+        let tiles_across = (self.world_size.0 as f64 / tile_size).ceil();
+        let tiles_down = (self.world_size.1 as f64 / tile_size).ceil();
+        let to_tile = |v : f64, max : f64| v.max(0f64).min(max) as u32;
+        (to_tile((offset_x / tile_size).floor(), tiles_across),
+         to_tile((offset_y / tile_size).floor(), tiles_down),
+         to_tile(((offset_x + view_w) / tile_size).ceil(), tiles_across),
+         to_tile(((offset_y + view_h) / tile_size).ceil(), tiles_down))
+    }
+
+    // whether any part of a rectangle in world coordinates is in view
+    pub fn is_visible(&self, rect : &Rectangle) -> bool {
+        let (offset_x, offset_y) = self.get_offset();
+        let (view_w, view_h) = self.get_view_size();
+        rect.overlaps(&Rectangle::new(offset_x, offset_y, view_w, view_h))
+    }
+}
+
+fn clamp_axis(value : f64, view : f64, world : f64) -> f64 {
+    if view >= world {
+        world / 2f64
+    } else {
+        value.max(view / 2f64).min(world - view / 2f64)
     }
 }
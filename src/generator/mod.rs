@@ -1,9 +1,13 @@
 extern crate rand;
 
-use world::grid::Grid;
+use world::grid::{Grid, Legend};
 
-use rand::thread_rng;
-use rand::{Rng, Rand};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use rustc_serialize::json;
+
+use rand::{Rng, Rand, SeedableRng, IsaacRng};
 use rand::distributions::Range;
 use rand::distributions::IndependentSample;
 
@@ -67,11 +71,18 @@ impl Direction {
     }
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Tile {
     Wall,
     Floor
 }
 
+pub fn template_legend() -> Legend<Tile> {
+    Legend::new()
+        .add('#', Tile::Wall)
+        .add('.', Tile::Floor)
+}
+
 #[derive (Clone, Copy, Debug)]
 enum TurnType {
     None,
@@ -101,7 +112,7 @@ impl TurnChanceConfig {
             turns[index] = TurnType::Right;
             index += 1;
         }
-        for _ in 0..left {
+        for _ in 0..u {
             turns[index] = TurnType::UTurn;
             index += 1;
         }
@@ -110,9 +121,8 @@ impl TurnChanceConfig {
             range : Range::new(0, 100)
         }
     }
-    pub fn random_turn(&self) -> TurnType {
-        let mut rng = rand::thread_rng();
-        let index = self.range.ind_sample(&mut rng);
+    pub fn random_turn<R : Rng>(&self, rng : &mut R) -> TurnType {
+        let index = self.range.ind_sample(rng);
         self.turns[index]
     }
 }
@@ -150,16 +160,15 @@ impl MakeRoomConfig {
             range : Range::new(0, 100)
         }
     }
-    pub fn random_room_type(&self) -> RoomType {
-        let mut rng = rand::thread_rng();
-        let index = self.range.ind_sample(&mut rng);
+    pub fn random_room_type<R : Rng>(&self, rng : &mut R) -> RoomType {
+        let index = self.range.ind_sample(rng);
         self.rooms[index]
     }
 }
 
 struct FloorMaker {
-    x : u32,
-    y : u32,
+    x : i32,
+    y : i32,
     direction : Direction,
     turn_chance_config : TurnChanceConfig,
     step_count : u32
@@ -169,18 +178,18 @@ struct FloorMaker {
 
 impl FloorMaker {
 
-    pub fn new(x : u32, y : u32) -> FloorMaker {
+    pub fn new<R : Rng>(x : i32, y : i32, config : &GeneratorConfig, rng : &mut R) -> FloorMaker {
         FloorMaker {
             x : x,
             y : y,
-            direction : rand::thread_rng().gen::<Direction>(),
-            turn_chance_config  : TurnChanceConfig::new(10, 10, 10),
+            direction : rng.gen::<Direction>(),
+            turn_chance_config  : TurnChanceConfig::new(config.turn_left, config.turn_right, config.u_turn),
             step_count : 0
         }
     }
 
-    pub fn step(&mut self) {
-        let turn_type = self.turn_chance_config.random_turn();
+    pub fn step<R : Rng>(&mut self, rng : &mut R) {
+        let turn_type = self.turn_chance_config.random_turn(rng);
         self.direction = self.direction.turn(turn_type);
         match self.direction {
             Direction::Up => self.y += 1,
@@ -192,7 +201,7 @@ impl FloorMaker {
     }
 }
 
-fn place_floor(x : u32, y : u32, grid : &mut Grid<Tile>) -> bool{
+fn place_floor(x : i32, y : i32, grid : &mut Grid<Tile>) -> bool{
     if grid.get(x, y).is_some() {
         match *grid.get(x, y).unwrap() {
             Tile::Wall => {
@@ -205,7 +214,7 @@ fn place_floor(x : u32, y : u32, grid : &mut Grid<Tile>) -> bool{
     false
 }
 
-fn place_room(room_type : RoomType, x_start : u32, y_start : u32, grid :  &mut Grid<Tile>) -> u32 {
+fn place_room(room_type : RoomType, x_start : i32, y_start : i32, grid :  &mut Grid<Tile>) -> u32 {
     let mut w = 0;
     let mut h = 0;
     let mut floor_count = 0;
@@ -231,26 +240,89 @@ fn place_room(room_type : RoomType, x_start : u32, y_start : u32, grid :  &mut G
     floor_count
 }
 
+// the knobs of the level generator, loaded from a JSON file so they can be tweaked while the game runs
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug)]
+pub struct GeneratorConfig {
+    // template size, in template cells
+    pub width : u32,
+    pub height : u32,
+    // tiles per template cell on each axis
+    pub cell_size : u32,
+    // chances in percent that a floor maker turns on a step
+    pub turn_left : u32,
+    pub turn_right : u32,
+    pub u_turn : u32,
+    // chances in percent that a floor maker places a room on a step
+    pub two_by_two_room : u32,
+    pub three_by_three_room : u32,
+    // the generator stops after placing this many floor cells
+    pub floor_count : u32,
+    // the same seed makes the same level, without one every level is different
+    pub seed : Option<u32>
+}
+
+impl GeneratorConfig {
+    pub fn new() -> GeneratorConfig {
+        GeneratorConfig {
+            width : 100,
+            height : 100,
+            cell_size : 3,
+            turn_left : 10,
+            turn_right : 10,
+            u_turn : 10,
+            two_by_two_room : 10,
+            three_by_three_room : 10,
+            floor_count : 110,
+            seed : None
+        }
+    }
+
+    pub fn load(path : &Path) -> Result<GeneratorConfig, String> {
+        let mut source = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut source)).map_err(|e| format!("{}: {}", path.display(), e)));
+        let config : GeneratorConfig = try!(json::decode(&source).map_err(|e| format!("{}: {}", path.display(), e)));
+
+        if config.turn_left + config.turn_right + config.u_turn > 100 {
+            return Err(format!("{}: the turn chances add up to more than 100", path.display()));
+        }
+        if config.two_by_two_room + config.three_by_three_room > 100 {
+            return Err(format!("{}: the room chances add up to more than 100", path.display()));
+        }
+        if config.width == 0 || config.height == 0 || config.cell_size == 0 {
+            return Err(format!("{}: the sizes must not be 0", path.display()));
+        }
+        // make_level places floor until it has more than floor_count cells, which never happens on a smaller template
+        if config.floor_count as u64 >= config.width as u64 * config.height as u64 {
+            return Err(format!("{}: floor_count must be less than width * height", path.display()));
+        }
+        Ok(config)
+    }
+}
+
 pub struct LevelTemplate {
     pub grid : Grid<Tile>,
-    pub start : (u32, u32)
+    pub start : (i32, i32),
+    // the seed the level was made with, also when the config has none
+    pub seed : u32
 }
 
-pub fn make_level(width : u32, height : u32) -> LevelTemplate {
+pub fn make_level(config : &GeneratorConfig) -> LevelTemplate {
+    let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen::<u32>());
+    // isaac gives the same numbers on every platform, unlike StdRng
+    let mut rng = IsaacRng::from_seed(&[seed][..]);
+
+    let width = config.width;
+    let height = config.height;
     let mut grid = Grid::<Tile>::new(width, height);
     // fill with walls
-    for y in 0..grid.height {
-        for x in 0..grid.width {
-            grid.set(x, y, Tile::Wall);
-        }
-    }
+    grid.fill_rect(0, 0, width, height, || Tile::Wall);
 
-    let make_room_config = MakeRoomConfig::new(10, 10);
+    let make_room_config = MakeRoomConfig::new(config.two_by_two_room, config.three_by_three_room);
     let mut floor_makers = Vec::<FloorMaker>::new();
 
-    let start = (width/2, height/2);
+    let start = ((width/2) as i32, (height/2) as i32);
 
-    floor_makers.push(FloorMaker::new(start.0, start.1));
+    floor_makers.push(FloorMaker::new(start.0, start.1, config, &mut rng));
 
     let mut done = false;
     let mut floor_count = 0;
@@ -263,14 +335,14 @@ pub fn make_level(width : u32, height : u32) -> LevelTemplate {
 
             if place_floor(floor_maker.x, floor_maker.y, &mut grid) {
                 floor_count += 1;
-                floor_count += place_room(make_room_config.random_room_type(), floor_maker.x, floor_maker.y, &mut grid);
+                floor_count += place_room(make_room_config.random_room_type(&mut rng), floor_maker.x, floor_maker.y, &mut grid);
             }
 
-            floor_maker.step();
+            floor_maker.step(&mut rng);
 
             // spawn new
-            if rand::thread_rng().gen_weighted_bool(chance_to_spawn_new_floor_maker) {
-                new_floor_makers.push(FloorMaker::new(floor_maker.x, floor_maker.y));
+            if rng.gen_weighted_bool(chance_to_spawn_new_floor_maker) {
+                new_floor_makers.push(FloorMaker::new(floor_maker.x, floor_maker.y, config, &mut rng));
             }
         }
 
@@ -280,7 +352,6 @@ pub fn make_level(width : u32, height : u32) -> LevelTemplate {
         // remove floor_makers
         let num_floor_makers = floor_makers.len();
         if num_floor_makers > 1 {
-            let mut rng = rand::thread_rng();
             let mut num_left = num_floor_makers;
             let chance_to_destroy = (100 - num_floor_makers * 10) as u32;
 
@@ -302,13 +373,56 @@ pub fn make_level(width : u32, height : u32) -> LevelTemplate {
             panic!("no floormakers left - shoudnt happen")
         }
 
-        if floor_count > 110 {
+        if floor_count > config.floor_count {
             done = true;
         }
     }
 
     LevelTemplate {
         grid : grid,
-        start : start
+        start : start,
+        seed : seed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use world::grid::Grid;
+
+    // the template of golden_config, regenerate it when the generator is changed on purpose
+    const GOLDEN_SEED_1 : &'static str = include_str!("golden_seed_1.txt");
+
+    fn golden_config() -> GeneratorConfig {
+        let mut config = GeneratorConfig::new();
+        config.width = 40;
+        config.height = 30;
+        config.seed = Some(1);
+        config
+    }
+
+    #[test]
+    fn same_seed_makes_the_golden_level() {
+        let level = make_level(&golden_config());
+        assert_eq!(level.seed, 1);
+        assert_eq!(level.grid.to_ascii(&template_legend()).unwrap(), GOLDEN_SEED_1);
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let legend = template_legend();
+        let grid = Grid::from_ascii(GOLDEN_SEED_1, &legend).unwrap();
+        assert_eq!((grid.width, grid.height), (40, 30));
+        assert_eq!(grid.to_ascii(&legend).unwrap(), GOLDEN_SEED_1);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let legend = template_legend();
+        let level = make_level(&golden_config());
+        let mut bytes = Vec::new();
+        level.grid.write_binary(&mut bytes, &legend).unwrap();
+        let grid = Grid::read_binary(&mut &bytes[..], &legend).unwrap();
+        assert_eq!(grid.to_ascii(&legend).unwrap(), GOLDEN_SEED_1);
     }
 }
@@ -1,54 +1,55 @@
 use generator;
 use world::*;
 use world::grid::Grid;
+use world::tilemap::*;
+
+const DUMP_TEMPLATE : bool = false;
 
 pub struct Level {
-    pub grid : Grid<Cell>,
-    pub start_tile : (u32, u32)
+    pub tile_map : TileMap,
+    pub start_tile : (i32, i32)
 }
 
-pub fn make_level(width : u32, height : u32) -> Level {
-    println!("make level");
-
-    let level = generator::make_level(width, height);
+pub fn make_level(config : &generator::GeneratorConfig) -> Level {
+    let level = generator::make_level(config);
     let template = level.grid;
 
-    let mut min_x = template.width;
-    let mut min_y = template.height;
+    if DUMP_TEMPLATE {
+        match template.to_ascii(&generator::template_legend()) {
+            Ok(ascii) => print!("{}", ascii),
+            Err(e) => println!("failed to dump the template: {}", e)
+        }
+    }
+
+    let mut min_x = template.width as i32;
+    let mut min_y = template.height as i32;
     let mut max_x = 0;
     let mut max_y = 0;
 
-    for y in 0..height {
-        for x in 0..width {
-            match *template.get(x, y).unwrap() {
-                generator::Tile::Floor => {
-                    if y < min_y {
-                        min_y = y;
-                    }
-                    if y >= max_y {
-                        max_y = y;
-                    }
-                    if x < min_x {
-                        min_x = x;
-                    }
-                    if x >= max_x {
-                        max_x = x;
-                    }
-                },
-                _ => {}
-            }
+    for (x, y, tile) in template.iter() {
+        match *tile {
+            generator::Tile::Floor => {
+                if y < min_y {
+                    min_y = y;
+                }
+                if y >= max_y {
+                    max_y = y;
+                }
+                if x < min_x {
+                    min_x = x;
+                }
+                if x >= max_x {
+                    max_x = x;
+                }
+            },
+            _ => {}
         }
     }
 
-    //println!("{:?} {:?} {:?} {:?}", min_x, max_x, min_y, max_y);
-    //println!("generating tiles");
-
-    let w = max_x - min_x + 3;
-    let h = max_y - min_y + 3;
-
-    println!("{:?} {:?} {:?} {:?} ", w, h, min_x, min_y);
+    let w = (max_x - min_x + 3) as u32;
+    let h = (max_y - min_y + 3) as u32;
 
-    let cell_size = 3;
+    let cell_size = config.cell_size;
 
     let mut grid = Grid::<Cell>::new(w * cell_size, h * cell_size);
 
@@ -61,33 +62,27 @@ pub fn make_level(width : u32, height : u32) -> Level {
 
 
             if tx == level.start.0 && ty == level.start.1 {
-                println!("whoot");
                 start = (x,  y);
-
-                println!("whoot {:?}", start);
             }
 
-            grid.fill(x, y, cell_size, cell_size, || {
-                let tile;
-                match *template.get(tx, ty).unwrap() {
-                    generator::Tile::Floor => {
-                        tile = Tile::Floor;
-                    },
-                    generator::Tile::Wall => {
-                        tile = Tile::Solid;
-                    }
+            // the border around the floor may lie outside the template
+            let floor = template.get(tx, ty).map_or(false, |t| *t == generator::Tile::Floor);
+            grid.fill_rect(x, y, cell_size, cell_size, || {
+                if floor {
+                    Cell::new(Tile::Floor)
+                } else {
+                    Cell::new(Tile::Solid)
                 }
-                Cell::new(tile)
             });
-            x += cell_size;
+            x += cell_size as i32;
         }
         x = 0;
-        y += cell_size;
+        y += cell_size as i32;
     }
 
     // "autotile"
-    for y in 0..grid.height {
-        for x in 0..grid.width {
+    for y in 0..grid.height as i32 {
+        for x in 0..grid.width as i32 {
             let mut below_is_floor = false;
             {
                 let below = grid.get(x, y + 1);
@@ -104,8 +99,40 @@ pub fn make_level(width : u32, height : u32) -> Level {
         }
     }
 
+    // split into layers, the top edge of a wall overhangs the floor above it
+    let mut tile_map = TileMap::with_default_layers(grid.width, grid.height);
+    for (x, y, cell) in grid.iter() {
+        let tile = cell.tile;
+        let above_is_floor = grid.get(x, y - 1).map_or(false, |cell| cell.tile == Tile::Floor);
+        match tile {
+            Tile::Solid if above_is_floor => {
+                tile_map.set(LAYER_GROUND, x, y, Cell::new(Tile::Floor));
+                tile_map.set(LAYER_OVERLAY, x, y, Cell::new(Tile::Solid));
+            },
+            Tile::Wall => {
+                // there is floor behind a wall once it is shot down
+                tile_map.set(LAYER_GROUND, x, y, Cell::new(Tile::Floor));
+                tile_map.set(LAYER_WALLS, x, y, Cell::new(tile));
+            },
+            Tile::Solid => {
+                tile_map.set(LAYER_WALLS, x, y, Cell::new(tile));
+            },
+            _ => {
+                tile_map.set(LAYER_GROUND, x, y, Cell::new(tile));
+            }
+        }
+    }
+
+    tile_map.seed = level.seed;
+    tile_map.spawn_points.push(SpawnPoint {
+        name : "start".to_string(),
+        kind : "player".to_string(),
+        x : (start.0 * 8) as f64,
+        y : (start.1 * 8) as f64
+    });
+
     Level {
-        grid : grid,
+        tile_map : tile_map,
         start_tile : start
     }
 }
@@ -2,31 +2,41 @@ extern crate sdl2;
 extern crate sdl2_image;
 extern crate rand;
 extern crate nalgebra as na;
+extern crate rustc_serialize;
 
 use na::Norm;
 use std::ops::{Add, Mul};
 
 use std::path::Path;
+use std::fs::File;
+use std::io::Write;
 
 use sdl2::pixels::Color;
 use sdl2::keyboard::{Keycode};
 
 mod motor;
-use motor::{MotorGraphics, TextureReference, MotorContext};
-use motor::gfx::{Animation, TextureRegion, SpriteBuilder, Sprite, NinePatch};
-use motor::font::BitmapFont;
+use motor::{MotorGraphics, MotorContext, MotorResult};
+use motor::gfx::{AnimationStateMachine, SpriteBuilder, Sprite, NinePatch};
+use motor::gfx::atlas::TextureAtlas;
+use motor::gfx::sheet::SpriteSheet;
+use motor::assets::{FontHandle, normalize};
 
 mod world;
-use world::grid::Grid;
+use world::tilemap::TileMap;
+use world::tiled;
+use world::light::Light;
 
 mod render;
 mod generator;
 mod camera;
 mod levelgenerator;
+use generator::GeneratorConfig;
+mod minimap;
 
 use world::*;
 use camera::*;
-use render::TileSet;
+use render::{TileSet, WorldRenderer};
+use minimap::Minimap;
 
 struct Bullet {
     entity : Entity,
@@ -34,6 +44,8 @@ struct Bullet {
     alive : bool
 }
 const BULLET_SIZE : f64 = 2f64;
+const BULLET_DAMAGE : u32 = 1;
+const TILE_SIZE : f64 = 8f64;
 
 impl Bullet {
     pub fn new(sprite : Sprite) -> Bullet {
@@ -46,11 +58,26 @@ impl Bullet {
 }
 
 impl Actor for Bullet {
-    fn update(&mut self, context : &mut motor::MotorContext, delta_time : f64, grid : &Grid<Cell>) -> Action {
+    fn update(&mut self, context : &mut motor::MotorContext, delta_time : f64, tile_map : &TileMap) -> Action {
         self.sprite.update(delta_time);
-        let collision = world::move_entity(&mut self.entity, delta_time, grid);
+        let velocity = self.entity.velocity;
+        let from = self.entity.get_center();
+        let collision = world::move_entity(&mut self.entity, delta_time, tile_map);
         if collision {
             self.alive = false;
+
+            // damage the first solid tile along the path the bullet wanted to travel
+            let direction = velocity.normalize();
+            let to = (from.0 + velocity.x * delta_time + direction.x * BULLET_SIZE,
+                      from.1 + velocity.y * delta_time + direction.y * BULLET_SIZE);
+            return match tile_map.raycast(from, to, TILE_SIZE) {
+                Some(hit) => Action::DamageTile {
+                    x : hit.tile_x,
+                    y : hit.tile_y,
+                    amount : BULLET_DAMAGE
+                },
+                None => Action::None
+            };
         }
         Action::None
     }
@@ -66,17 +93,44 @@ impl Actor for Bullet {
     fn get_sprite(&self) -> &Sprite {
         &self.sprite
     }
+    fn get_light(&self) -> Option<Light> {
+        let (x, y) = self.entity.get_center();
+        Some(Light::new(x, y, (255, 220, 120), 12f64))
+    }
 }
 
 
+const LEVEL_FILE : &'static str = "level.tmx";
+const LEVEL_ASSETS : &'static str = "assets/level_assets.png";
+const GENERATOR_CONFIG : &'static str = "assets/generator.json";
+const SNAPSHOT_FILE : &'static str = "snapshot.txt";
+const SNAPSHOT_BINARY_FILE : &'static str = "snapshot.grd";
+
 const FIRE_INTERVAL : f64 = 0.1f64;
+const IMPACT_TRAUMA : f64 = 0.15f64;
+const ZOOM_STEP : f64 = 2f64;
+const MINIMAP_TILES_PER_PIXEL : u32 = 4;
+const MINIMAP_BORDER : u32 = 3;
+const PLAYER_HEALTH : f64 = 100f64;
+// the player is always drawn over bullets
+const PLAYER_RENDER_LAYER : i32 = 1;
+
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum PlayerState {
+    Idle,
+    Walk
+}
 
+// below this speed the player counts as standing still
+const WALK_SPEED : f64 = 4f64;
 
 struct Player {
     entity : Entity,
     sprite : Sprite,
-    alive : bool,
-    fire_cooldown : f64
+    animation_state : AnimationStateMachine<PlayerState>,
+    fire_cooldown : f64,
+    health : f64
 }
 
 impl Player {
@@ -84,14 +138,17 @@ impl Player {
         Player {
             entity : Entity::new(8f64, 8f64),
             sprite : sprite,
-            alive : true,
-            fire_cooldown : 0f64
+            animation_state : AnimationStateMachine::new()
+                .state(PlayerState::Idle, "idle")
+                .state(PlayerState::Walk, "walk"),
+            fire_cooldown : 0f64,
+            health : PLAYER_HEALTH
         }
     }
 }
 
 impl Actor for Player {
-    fn update(&mut self, context : &mut motor::MotorContext, delta_time : f64, grid : &Grid<Cell>) -> Action {
+    fn update(&mut self, context : &mut motor::MotorContext, delta_time : f64, tile_map : &TileMap) -> Action {
 
         let mut action = Action::None;
 
@@ -111,16 +168,34 @@ impl Actor for Player {
             self.entity.velocity.y += acceleration;
         }
         if context.keyboard.is_key_pressed(Keycode::K) {
-            self.alive = false;
+            self.health = 0f64;
         }
         if context.keyboard.is_key_pressed(Keycode::D) {
             context.draw_debug_boxes = !context.draw_debug_boxes;
         }
-        world::move_entity(&mut self.entity, delta_time, grid);
+        world::move_entity(&mut self.entity, delta_time, tile_map);
+
+        let speed = self.entity.velocity.norm();
+        let state = if speed > WALK_SPEED { PlayerState::Walk } else { PlayerState::Idle };
+        self.animation_state.update(&mut self.sprite, state);
 
+        // keep facing the same way when moving straight up or down
+        if self.entity.velocity.x < 0f64 {
+            self.sprite.flip_h = true;
+        } else if self.entity.velocity.x > 0f64 {
+            self.sprite.flip_h = false;
+        }
 
-        let friction = 0.87f64;
-        self.entity.velocity = self.entity.velocity.mul(friction);
+        let center = self.entity.get_center();
+        let tile_def = tile_map.get_tile_def_at_position(center.0, center.1, TILE_SIZE);
+
+        self.entity.velocity = self.entity.velocity.mul(tile_def.friction);
+
+        self.health -= tile_def.damage_per_second * delta_time;
+        if self.health <= 0f64 {
+            self.health = PLAYER_HEALTH;
+            return Action::PlayerDied;
+        }
 
 
         self.fire_cooldown = self.fire_cooldown - delta_time;
@@ -146,8 +221,9 @@ impl Actor for Player {
 
         return action;
     }
+    // the player is always the first actor, so it is never removed from the world
     fn is_alive(&self) -> bool {
-        self.alive
+        true
     }
     fn get_entity(&self) -> &Entity {
         &self.entity
@@ -158,12 +234,19 @@ impl Actor for Player {
     fn get_sprite(&self) -> &Sprite {
         &self.sprite
     }
+    fn get_light(&self) -> Option<Light> {
+        let (x, y) = self.entity.get_center();
+        Some(Light::new(x, y, (255, 230, 200), 64f64))
+    }
+    fn get_render_layer(&self) -> i32 {
+        PLAYER_RENDER_LAYER
+    }
 }
 
 struct Assets {
     tile_set : TileSet,
-    font : BitmapFont,
-    monster_texture : TextureReference,
+    font : FontHandle,
+    monsters : TextureAtlas,
     nine_patch : NinePatch
 }
 
@@ -171,13 +254,21 @@ struct App {
     state_time : f64,
     assets : Option<Assets>,
     controller_id : Option<i32>,
-    camera : Camera,
+    display_size : (u32, u32),
+    cameras : Vec<Camera>,
+    split_screen : bool,
+    world_renderer : WorldRenderer,
+    minimap : Minimap,
+    show_minimap : bool,
+    generator_config : GeneratorConfig,
+    // where the player respawns after dying
+    player_start : (f64, f64),
     world : Option<World>
 }
 
 fn make_bullet(assets : &Assets, x : f64, y: f64, velocity_x: f64, velocity_y : f64) -> Bullet {
-    let bullet_sprite = SpriteBuilder::new(assets.monster_texture.clone())
-                .texture_region(TextureRegion::new(11, 2, BULLET_SIZE as u32, BULLET_SIZE as u32))
+    let bullet_sprite = SpriteBuilder::new(assets.monsters.texture.clone())
+                .texture_region(assets.monsters.region("bullet").expect("no bullet region"))
                 .build();
 
     let mut bullet = Bullet::new(bullet_sprite);
@@ -185,56 +276,134 @@ fn make_bullet(assets : &Assets, x : f64, y: f64, velocity_x: f64, velocity_y :
     bullet.entity.position.y = y;
     bullet.entity.velocity.x = velocity_x;
     bullet.entity.velocity.y = velocity_y;
+    bullet.sprite.rotation = velocity_y.atan2(velocity_x).to_degrees();
 
     bullet
 }
 
+// returns where the player starts
+fn start_level(level : levelgenerator::Level, world : &mut World, cameras : &mut [Camera], tile_set : &TileSet) -> (f64, f64) {
+    let start_position = (level.start_tile.0 as f64 * 8f64, level.start_tile.1 as f64 * 8f64);
+    println!("start: {:?}", start_position);
+    world.actors[0].get_entity_mut().set_position(start_position.0, start_position.1);
+
+    let mut tile_map = level.tile_map;
+    let seed = tile_map.seed;
+    for layer in tile_map.layers_mut() {
+        tile_set.decorate(&mut layer.grid, seed);
+    }
+    let (x, y) = world.actors[0].get_entity().get_center();
+    for camera in cameras.iter_mut() {
+        camera.set_world_size(tile_map.width * 8, tile_map.height * 8);
+        camera.set_position(x, y);
+    }
+    world.init(tile_map, TILE_SIZE);
+    start_position
+}
+
+fn make_camera(display_size : (u32, u32)) -> Camera {
+    let mut camera = Camera::new(display_size);
+    camera.follow_mode = FollowMode::Spring { stiffness : 60f64, damping : 14f64 };
+    camera.deadzone = (16f64, 12f64);
+    camera.look_ahead = 0.15f64;
+    camera
+}
+
+fn add_trauma(cameras : &mut [Camera], amount : f64) {
+    for camera in cameras.iter_mut() {
+        camera.add_trauma(amount);
+    }
+}
+
 impl App {
     pub fn new(display_size : (u32, u32)) -> App {
         App {
             state_time : 0f64,
             assets : None,
             controller_id : None,
-            camera : Camera::new(display_size),
+            display_size : display_size,
+            cameras : vec![make_camera(display_size)],
+            split_screen : false,
+            world_renderer : WorldRenderer::new(TILE_SIZE as i32),
+            minimap : Minimap::new(MINIMAP_TILES_PER_PIXEL, MINIMAP_BORDER),
+            show_minimap : true,
+            generator_config : GeneratorConfig::new(),
+            player_start : (0f64, 0f64),
             world : None
         }
     }
 
-
-
+    // split-screen shows a close view on the left and a wide one on the right
+    fn toggle_split_screen(&mut self) {
+        self.split_screen = !self.split_screen;
+        let (w, h) = self.display_size;
+        if self.split_screen {
+            let mut wide = self.cameras[0].clone();
+            let (x, y) = wide.position;
+            let zoom = wide.zoom / ZOOM_STEP;
+            wide.set_zoom(zoom);
+            wide.set_viewport((w / 2) as i32, 0, w - w / 2, h);
+            wide.set_position(x, y);
+            self.cameras[0].set_viewport(0, 0, w / 2, h);
+            self.cameras.push(wide);
+        } else {
+            self.cameras.truncate(1);
+            self.cameras[0].set_viewport(0, 0, w, h);
+        }
+    }
 }
 
 impl motor::MotorApp for App {
-    fn init(&mut self, context : &mut motor::MotorContext) {
+    fn init(&mut self, context : &mut motor::MotorContext) -> MotorResult<()> {
         context.renderer.set_draw_color(Color::RGB(0, 0, 0));
 
-        let mut tile_set = TileSet::new(context.load_texture(&Path::new("assets/level_assets.png")));
-        tile_set.add_tile(Tile::Grass, TextureRegion::new(0, 0, 8, 8));
-        tile_set.add_tile(Tile::Water, TextureRegion::new(0, 8, 8, 8));
-        tile_set.add_tile(Tile::Solid, TextureRegion::new(0,16,8,8));
-        tile_set.add_tile(Tile::Wall, TextureRegion::new(8,16,8,8));
-        tile_set.add_tile(Tile::Floor, TextureRegion::new(64,0,8,8));
+        // reload changed assets while developing
+        context.assets.hot_reload = cfg!(debug_assertions);
+        context.assets.watch(&Path::new(GENERATOR_CONFIG));
+        match GeneratorConfig::load(&Path::new(GENERATOR_CONFIG)) {
+            Ok(config) => self.generator_config = config,
+            Err(e) => println!("using the default generator config: {}", e)
+        }
 
-        let nine_patch = NinePatch::new(context.load_texture_as_ref(&Path::new("assets/level_assets.png")),
-                                        TextureRegion::new(0, 8, 8, 8),
+        let level_texture = try!(context.get_texture(&Path::new(LEVEL_ASSETS)));
+        let level_sheet = SpriteSheet::new(level_texture, 8, 8);
+        let cell = |column, row| level_sheet.region(column, row).expect("level_assets.png is too small");
+        let mut tile_set = TileSet::new(level_sheet.texture.clone());
+        tile_set.add_tile(Tile::Grass, cell(0, 0));
+        tile_set.add_tile(Tile::Water, cell(0, 1));
+        tile_set.add_tile(Tile::Solid, cell(0, 2));
+        tile_set.add_tile(Tile::Wall, cell(1, 2));
+        tile_set.add_tile(Tile::Lava, cell(0, 3));
+        tile_set.add_tile(Tile::Ice, cell(1, 3));
+        tile_set.add_tile_variant(Tile::Floor, cell(8, 0), 8);
+        tile_set.add_tile_variant(Tile::Floor, cell(9, 0), 1);
+        tile_set.add_tile_variant(Tile::Floor, cell(10, 0), 1);
+        tile_set.add_decoration(Tile::Floor, cell(11, 0), 2);
+        tile_set.add_decoration(Tile::Floor, cell(12, 0), 1);
+        tile_set.set_decoration_chance(5);
+
+        let nine_patch = NinePatch::new(level_sheet.texture.clone(),
+                                        cell(0, 1),
                                         3, 3, 3, 3);
 
         let assets = Assets {
             tile_set : tile_set,
-            font : context.load_font(&Path::new("assets/04b_03.fnt")),
-            monster_texture : context.load_texture_as_ref(&Path::new("assets/monster_assets.png")),
+            font : try!(context.get_font(&Path::new("assets/04b_03.fnt"))),
+            monsters : try!(context.load_atlas(&Path::new("assets/monster_assets.json"))),
             nine_patch : nine_patch
         };
 
         let mut world = World::new();
 
-        let player_sprite = SpriteBuilder::new(assets.monster_texture.clone())
-                    .animation(Animation::new(0.5f64, vec![TextureRegion::new(0, 0, 8, 8), TextureRegion::new(0, 8, 8, 8)]))
+        let player_sprite = SpriteBuilder::new(assets.monsters.texture.clone())
+                    .named_animation("idle", assets.monsters.animation("player_idle").expect("no player_idle animation"))
+                    .named_animation("walk", assets.monsters.animation("player_walk").expect("no player_walk animation"))
                     .build();
         world.actors.push(Box::new(Player::new(player_sprite)));
 
         self.assets = Some(assets);
         self.world = Some(world);
+        Ok(())
     }
 
     fn update(&mut self, context : &mut motor::MotorContext, delta_time : f64) -> bool {
@@ -243,25 +412,113 @@ impl motor::MotorApp for App {
             done = true;
         }
 
+        if context.keyboard.is_key_just_pressed(Keycode::Tab) {
+            self.toggle_split_screen();
+        }
+
         self.state_time += delta_time;
+
+        let changed = context.assets.poll(delta_time, &context.renderer);
+        if !changed.is_empty() {
+            self.world_renderer.invalidate();
+            if changed.contains(&normalize(&Path::new(GENERATOR_CONFIG))) {
+                match GeneratorConfig::load(&Path::new(GENERATOR_CONFIG)) {
+                    Ok(config) => {
+                        println!("reloaded {}", GENERATOR_CONFIG);
+                        self.generator_config = config;
+                    },
+                    Err(e) => println!("failed to reload {}", e)
+                }
+            }
+        }
+
         let assets = self.assets.as_mut().unwrap();
         let world = self.world.as_mut().unwrap();
 
         if context.keyboard.is_key_pressed(Keycode::R) {
-            let level = levelgenerator::make_level(100, 100);
-            {
-                let start_position = (level.start_tile.0 as f64 * 8f64, level.start_tile.1 as f64 * 8f64);
-                println!("start: {:?}", start_position);
-                world.actors[0].get_entity_mut().set_position(start_position.0, start_position.1);
+            let level = levelgenerator::make_level(&self.generator_config);
+            self.player_start = start_level(level, world, &mut self.cameras, &assets.tile_set);
+        }
+
+        if context.keyboard.is_key_just_pressed(Keycode::F5) && world.tile_map.is_some() {
+            match tiled::save_tile_map(world.tile_map.as_ref().unwrap(), &Path::new(LEVEL_FILE)) {
+                Ok(_) => println!("saved {}", LEVEL_FILE),
+                Err(e) => println!("failed to save level: {}", e)
             }
+        }
 
-            let grid = level.grid;
-            self.camera.set_world_size(grid.width * 8, grid.height * 8);
-            world.init(grid);
+        if context.keyboard.is_key_just_pressed(Keycode::F6) && world.tile_map.is_some() {
+            let snapshot = world.tile_map.as_ref().unwrap().to_ascii();
+            match snapshot.and_then(|s| File::create(SNAPSHOT_FILE).and_then(|mut f| f.write_all(s.as_bytes())).map_err(|e| format!("{}", e))) {
+                Ok(_) => println!("saved {}", SNAPSHOT_FILE),
+                Err(e) => println!("failed to save snapshot: {}", e)
+            }
+            match File::create(SNAPSHOT_BINARY_FILE).and_then(|mut f| world.tile_map.as_ref().unwrap().write_snapshot(&mut f)) {
+                Ok(_) => println!("saved {}", SNAPSHOT_BINARY_FILE),
+                Err(e) => println!("failed to save snapshot: {}", e)
+            }
         }
 
-        if world.grid.is_some() {
-            render::render_world(context, &world, &assets.tile_set, &self.camera);
+        // restores the tiles of the last snapshot, the player stays where they are
+        if context.keyboard.is_key_just_pressed(Keycode::F7) {
+            match File::open(SNAPSHOT_BINARY_FILE).and_then(|mut f| TileMap::read_snapshot(&mut f)) {
+                Ok(tile_map) => {
+                    let position = world.actors[0].get_entity().position;
+                    let level = levelgenerator::Level {
+                        tile_map : tile_map,
+                        start_tile : ((position.x / TILE_SIZE).floor() as i32, (position.y / TILE_SIZE).floor() as i32)
+                    };
+                    self.player_start = start_level(level, world, &mut self.cameras, &assets.tile_set);
+                },
+                Err(e) => println!("failed to load snapshot: {}", e)
+            }
+        }
+
+        if context.keyboard.is_key_just_pressed(Keycode::Equals) {
+            let zoom = self.cameras[0].zoom * ZOOM_STEP;
+            self.cameras[0].set_zoom(zoom);
+        }
+        if context.keyboard.is_key_just_pressed(Keycode::Minus) {
+            let zoom = self.cameras[0].zoom / ZOOM_STEP;
+            self.cameras[0].set_zoom(zoom);
+        }
+
+        if context.keyboard.is_key_just_pressed(Keycode::M) {
+            self.show_minimap = !self.show_minimap;
+        }
+
+        if context.keyboard.is_key_just_pressed(Keycode::F3) {
+            context.assets.print();
+        }
+
+        if context.keyboard.is_key_just_pressed(Keycode::F9) {
+            match tiled::load_level(&Path::new(LEVEL_FILE)) {
+                Ok(level) => self.player_start = start_level(level, world, &mut self.cameras, &assets.tile_set),
+                Err(e) => println!("failed to load level: {}", e)
+            }
+        }
+
+        if world.tile_map.is_some() {
+            let changes = world.tile_map.as_mut().unwrap().take_changes();
+            if !changes.is_empty() {
+                self.minimap.invalidate();
+                match world.fov.as_mut() {
+                    Some(fov) => fov.invalidate(),
+                    None => {}
+                }
+                world.refresh_tile_lights(TILE_SIZE);
+            }
+            match self.world_renderer.update(context, &world, &assets.tile_set, &changes) {
+                Ok(_) => {
+                    for camera in self.cameras.iter() {
+                        self.world_renderer.render(context, &world, camera);
+                    }
+                },
+                Err(e) => {
+                    println!("{}", e);
+                    done = true;
+                }
+            }
         }
 
         if self.controller_id.is_none() {
@@ -276,16 +533,41 @@ impl motor::MotorApp for App {
                     let bullet = make_bullet(&assets, x, y, velocity_x, velocity_y);
                     world.actors.push(Box::new(bullet));
                 },
+                Action::DamageTile { x, y, amount } => {
+                    if world.tile_map.as_mut().unwrap().damage(x, y, amount) {
+                        add_trauma(&mut self.cameras, IMPACT_TRAUMA * 2f64);
+                    } else {
+                        add_trauma(&mut self.cameras, IMPACT_TRAUMA);
+                    }
+                },
+                Action::PlayerDied => {
+                    println!("the player died");
+                    let (x, y) = self.player_start;
+                    let entity = world.actors[0].get_entity_mut();
+                    entity.set_position(x, y);
+                    entity.velocity = na::zero();
+                    let (center_x, center_y) = entity.get_center();
+                    for camera in self.cameras.iter_mut() {
+                        camera.set_position(center_x, center_y);
+                    }
+                },
                 _ => {}
             }
         }
         actions.clear();
 
-        let p = &world.actors[0];
-        let pos = p.get_entity().position;
-        self.camera.set_position(pos.x, pos.y);
+        let (pos, center, velocity) = {
+            let entity = world.actors[0].get_entity();
+            (entity.position, entity.get_center(), entity.velocity)
+        };
+        for camera in self.cameras.iter_mut() {
+            camera.follow(center.0, center.1, velocity.x, velocity.y);
+            camera.update(delta_time);
+        }
+        world.update_fov(center.0, center.1, TILE_SIZE);
+        world.update_lights(TILE_SIZE);
 
-        let font = &assets.font;
+        let font = assets.font.borrow();
         context.render_nine_patch(&assets.nine_patch, 1, 0, 47, 20);
         font.draw_str("Ninepatch", 5, 6, &mut context.renderer);
 
@@ -295,6 +577,18 @@ impl motor::MotorApp for App {
         y += font.line_height;
         font.draw_string(format!("y:{:.*}", 5,  pos.y), x, y, &mut context.renderer);
 
+        if self.show_minimap && world.tile_map.is_some() {
+            let (minimap_w, _) = self.minimap.get_size(world.tile_map.as_ref().unwrap());
+            let minimap_x = self.display_size.0 as i32 - (minimap_w + MINIMAP_BORDER * 2) as i32 - 1;
+            match self.minimap.render(context, &world, &assets.nine_patch, (minimap_x, 1), TILE_SIZE) {
+                Ok(_) => {},
+                Err(e) => {
+                    println!("{}", e);
+                    done = true;
+                }
+            }
+        }
+
         return done;
     }
 }
@@ -302,5 +596,11 @@ impl motor::MotorApp for App {
 pub fn main() {
     let display_size = (200, 150);
     let mut app = App::new(display_size);
-    motor::motor_start("rust-sdl2-game", (800, 600), Some(display_size), &mut app)
+    match motor::motor_start("rust-sdl2-game", (800, 600), Some(display_size), &mut app) {
+        Ok(_) => {},
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,152 @@
+use sdl2::render::Texture;
+
+use motor::{MotorContext, MotorGraphics, MotorError, MotorResult};
+use motor::gfx::{NinePatch, TextureRegion};
+use world::{World, Tile, Cell};
+use world::tilemap::TileMap;
+use world::fov::{FieldOfView, Visibility};
+
+const PLAYER_COLOR : (u8, u8, u8) = (255, 255, 255);
+const ACTOR_COLOR : (u8, u8, u8) = (255, 60, 60);
+const REMEMBERED_BRIGHTNESS : u32 = 128;
+
+pub fn tile_color(tile : &Tile) -> (u8, u8, u8) {
+    match *tile {
+        Tile::Grass => (40, 120, 40),
+        Tile::Water => (40, 70, 180),
+        Tile::Solid => (90, 90, 90),
+        Tile::Wall => (140, 120, 100),
+        Tile::Floor => (60, 50, 40),
+        Tile::Lava => (230, 90, 20),
+        Tile::Ice => (170, 210, 240)
+    }
+}
+
+// an overview of the level, cached in a texture that is only redrawn when invalidated
+// or when the explored part of the level grows
+pub struct Minimap {
+    // how many tiles each minimap pixel covers on both axes
+    pub tiles_per_pixel : u32,
+    // width of the nine patch border around the map
+    pub frame_border : u32,
+    texture : Option<Texture>,
+    size : (u32, u32),
+    dirty : bool,
+    fov_origin : Option<(i32, i32)>
+}
+
+impl Minimap {
+    pub fn new(tiles_per_pixel : u32, frame_border : u32) -> Minimap {
+        Minimap {
+            tiles_per_pixel : tiles_per_pixel,
+            frame_border : frame_border,
+            texture : None,
+            size : (0, 0),
+            dirty : true,
+            fov_origin : None
+        }
+    }
+
+    // call when the level was replaced or its tiles changed
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    // the size of the map in pixels, the frame not included
+    pub fn get_size(&self, tile_map : &TileMap) -> (u32, u32) {
+        let tpp = self.tiles_per_pixel;
+        ((tile_map.width + tpp - 1) / tpp, (tile_map.height + tpp - 1) / tpp)
+    }
+
+    pub fn render(&mut self, context : &mut MotorContext, world : &World, frame : &NinePatch, position : (i32, i32), tile_size : f64) -> MotorResult<()> {
+        let tile_map = match world.tile_map.as_ref() {
+            Some(tile_map) => tile_map,
+            None => return Ok(())
+        };
+        let fov = world.fov.as_ref();
+
+        try!(self.refresh(context, tile_map, fov));
+
+        let (w, h) = self.size;
+        let border = self.frame_border;
+        context.render_nine_patch(frame, position.0, position.1, w + border * 2, h + border * 2);
+
+        let map_x = position.0 + border as i32;
+        let map_y = position.1 + border as i32;
+        match self.texture {
+            Some(ref texture) => context.render(texture, &TextureRegion::new(0, 0, w, h), (map_x, map_y)),
+            None => {}
+        }
+
+        // the first actor is the player
+        let pixel_size = tile_size * self.tiles_per_pixel as f64;
+        for (index, actor) in world.actors.iter().enumerate() {
+            let (x, y) = actor.get_entity().get_center();
+            let tile_x = (x / tile_size).floor() as i32;
+            let tile_y = (y / tile_size).floor() as i32;
+            if !fov.map_or(true, |f| f.is_visible(tile_x, tile_y)) {
+                continue;
+            }
+            let color = if index == 0 { PLAYER_COLOR } else { ACTOR_COLOR };
+            let marker_x = map_x + (x / pixel_size).floor() as i32;
+            let marker_y = map_y + (y / pixel_size).floor() as i32;
+            context.fill_rect(marker_x, marker_y, 1, 1, color);
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self, context : &mut MotorContext, tile_map : &TileMap, fov : Option<&FieldOfView>) -> MotorResult<()> {
+        let size = self.get_size(tile_map);
+        if self.texture.is_none() || self.size != size {
+            self.texture = Some(try!(context.create_streaming_texture(size.0, size.1)));
+            self.size = size;
+            self.dirty = true;
+        }
+
+        // the explored area can only change when the field of view moved
+        let fov_origin = fov.and_then(|f| f.get_origin());
+        if !self.dirty && fov_origin == self.fov_origin {
+            return Ok(());
+        }
+        self.dirty = false;
+        self.fov_origin = fov_origin;
+
+        let tpp = self.tiles_per_pixel as i32;
+        let (w, h) = size;
+        let texture = self.texture.as_mut().unwrap();
+        try!(texture.with_lock(None, |buffer : &mut [u8], pitch : usize| {
+            for py in 0..h {
+                for px in 0..w {
+                    // sample the center of the tiles covered by the pixel
+                    let x = px as i32 * tpp + tpp / 2;
+                    let y = py as i32 * tpp + tpp / 2;
+                    let color = get_pixel_color(tile_map, fov, x, y);
+                    let offset = py as usize * pitch + px as usize * 3;
+                    buffer[offset] = color.0;
+                    buffer[offset + 1] = color.1;
+                    buffer[offset + 2] = color.2;
+                }
+            }
+        }).map_err(|e| MotorError::TextureLock(format!("{}", e))));
+        Ok(())
+    }
+}
+
+fn get_pixel_color(tile_map : &TileMap, fov : Option<&FieldOfView>, x : i32, y : i32) -> (u8, u8, u8) {
+    let brightness = match fov.map_or(Visibility::Visible, |f| f.get_visibility(x, y)) {
+        Visibility::Unexplored => return (0, 0, 0),
+        Visibility::Remembered => REMEMBERED_BRIGHTNESS,
+        Visibility::Visible => 255
+    };
+    match get_top_cell(tile_map, x, y) {
+        Some(cell) => {
+            let (r, g, b) = tile_color(&cell.tile);
+            ((r as u32 * brightness / 255) as u8, (g as u32 * brightness / 255) as u8, (b as u32 * brightness / 255) as u8)
+        },
+        None => (0, 0, 0)
+    }
+}
+
+fn get_top_cell(tile_map : &TileMap, x : i32, y : i32) -> Option<&Cell> {
+    tile_map.layers().iter().rev().filter_map(|l| l.grid.get(x, y)).next()
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::SystemTime;
+
+use sdl2::render::{Renderer, Texture};
+use sdl2_image::LoadTexture;
+
+use motor::font::BitmapFont;
+use motor::error::{MotorError, MotorResult};
+
+// assets are shared by reference counting, the manager holds one reference itself
+pub type TextureHandle = Rc<RefCell<Texture>>;
+pub type FontHandle = Rc<RefCell<BitmapFont>>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AssetKind {
+    Texture,
+    Font
+}
+
+pub struct AssetInfo {
+    pub kind : AssetKind,
+    pub path : PathBuf,
+    // references held outside the manager
+    pub users : usize
+}
+
+const RELOAD_INTERVAL : f64 = 1f64;
+
+// loads every file once and hands out shared handles to it.
+// with hot_reload set, changed files are loaded again in place so existing handles see the new version
+pub struct AssetManager {
+    pub hot_reload : bool,
+    textures : HashMap<PathBuf, TextureHandle>,
+    fonts : HashMap<PathBuf, FontHandle>,
+    // the fonts using each font page image, a changed page reloads them
+    font_pages : HashMap<PathBuf, Vec<PathBuf>>,
+    // the modification time of every watched file when it was last loaded
+    watched : HashMap<PathBuf, Option<SystemTime>>,
+    poll_time : f64
+}
+
+impl AssetManager {
+    pub fn new() -> AssetManager {
+        AssetManager {
+            hot_reload : false,
+            textures : HashMap::new(),
+            fonts : HashMap::new(),
+            font_pages : HashMap::new(),
+            watched : HashMap::new(),
+            poll_time : 0f64
+        }
+    }
+
+    // watches a file the manager doesn't load itself, changes are reported by poll
+    pub fn watch(&mut self, path : &Path) {
+        let key = normalize(path);
+        let modified = get_modified(&key);
+        self.watched.insert(key, modified);
+    }
+
+    // checks the watched files every RELOAD_INTERVAL seconds and reloads the changed textures and fonts.
+    // returns every changed file, the ones the caller watched itself included
+    pub fn poll(&mut self, delta_time : f64, renderer : &Renderer) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        if !self.hot_reload {
+            return changed;
+        }
+        self.poll_time += delta_time;
+        if self.poll_time < RELOAD_INTERVAL {
+            return changed;
+        }
+        self.poll_time = 0f64;
+
+        for (path, modified) in self.watched.iter_mut() {
+            let current = get_modified(path);
+            if current != *modified {
+                *modified = current;
+                changed.push(path.clone());
+            }
+        }
+
+        for path in changed.iter() {
+            match self.reload(path, renderer) {
+                Ok(_) => {},
+                // keep the old version, the file may be half written
+                Err(e) => println!("reload failed, {}", e)
+            }
+        }
+        changed
+    }
+
+    fn reload(&mut self, path : &Path, renderer : &Renderer) -> MotorResult<()> {
+        match self.textures.get(path) {
+            Some(texture) => {
+                let new_texture = try!(load_texture(path, renderer));
+                *texture.borrow_mut() = new_texture;
+                println!("reloaded {}", path.display());
+            },
+            None => {}
+        }
+        let mut font_paths = self.font_pages.get(path).cloned().unwrap_or(Vec::new());
+        if self.fonts.contains_key(path) {
+            font_paths.push(path.to_path_buf());
+        }
+        for font_path in font_paths.iter() {
+            let new_font = try!(BitmapFont::load(font_path, renderer));
+            // the new version may use other pages
+            self.unwatch_font_pages(font_path);
+            self.watch_font_pages(font_path, &new_font);
+            match self.fonts.get(font_path) {
+                Some(font) => *font.borrow_mut() = new_font,
+                None => {}
+            }
+            println!("reloaded {}", font_path.display());
+        }
+        Ok(())
+    }
+
+    fn watch_font_pages(&mut self, font_path : &Path, font : &BitmapFont) {
+        for page_path in font.get_page_paths() {
+            let key = normalize(page_path);
+            if !self.watched.contains_key(&key) {
+                self.watch(&key);
+            }
+            let fonts = self.font_pages.entry(key).or_insert(Vec::new());
+            if !fonts.iter().any(|f| f == font_path) {
+                fonts.push(font_path.to_path_buf());
+            }
+        }
+    }
+
+    fn unwatch_font_pages(&mut self, font_path : &Path) {
+        for fonts in self.font_pages.values_mut() {
+            fonts.retain(|f| f != font_path);
+        }
+        let unused : Vec<PathBuf> = self.font_pages.iter().filter(|&(_, f)| f.is_empty()).map(|(p, _)| p.clone()).collect();
+        for page_path in unused.iter() {
+            self.font_pages.remove(page_path);
+            // the page may also be loaded as a texture of its own
+            if !self.textures.contains_key(page_path) {
+                self.watched.remove(page_path);
+            }
+        }
+    }
+
+    pub fn texture(&mut self, path : &Path, renderer : &Renderer) -> MotorResult<TextureHandle> {
+        let key = normalize(path);
+        match self.textures.get(&key) {
+            Some(texture) => return Ok(texture.clone()),
+            None => {}
+        }
+        let texture = try!(load_texture(&key, renderer));
+        let handle = Rc::new(RefCell::new(texture));
+        self.watch(&key);
+        self.textures.insert(key, handle.clone());
+        Ok(handle)
+    }
+
+    pub fn font(&mut self, path : &Path, renderer : &Renderer) -> MotorResult<FontHandle> {
+        let key = normalize(path);
+        match self.fonts.get(&key) {
+            Some(font) => return Ok(font.clone()),
+            None => {}
+        }
+        let font = try!(BitmapFont::load(&key, renderer));
+        self.watch(&key);
+        self.watch_font_pages(&key, &font);
+        let handle = Rc::new(RefCell::new(font));
+        self.fonts.insert(key, handle.clone());
+        Ok(handle)
+    }
+
+    pub fn is_loaded(&self, path : &Path) -> bool {
+        let key = normalize(path);
+        self.textures.contains_key(&key) || self.fonts.contains_key(&key)
+    }
+
+    pub fn list(&self) -> Vec<AssetInfo> {
+        let mut assets : Vec<AssetInfo> = self.textures.iter()
+            .map(|(path, t)| AssetInfo { kind : AssetKind::Texture, path : path.clone(), users : Rc::strong_count(t) - 1 })
+            .chain(self.fonts.iter()
+                .map(|(path, f)| AssetInfo { kind : AssetKind::Font, path : path.clone(), users : Rc::strong_count(f) - 1 }))
+            .collect();
+        assets.sort_by(|a, b| a.path.cmp(&b.path));
+        assets
+    }
+
+    pub fn print(&self) {
+        for asset in self.list() {
+            println!("{:?} {} ({} users)", asset.kind, asset.path.display(), asset.users);
+        }
+    }
+
+    // drops the assets nobody else uses anymore, returns how many were dropped
+    pub fn collect_unused(&mut self) -> usize {
+        let unused_textures : Vec<PathBuf> = self.textures.iter().filter(|&(_, t)| Rc::strong_count(t) == 1).map(|(p, _)| p.clone()).collect();
+        let unused_fonts : Vec<PathBuf> = self.fonts.iter().filter(|&(_, f)| Rc::strong_count(f) == 1).map(|(p, _)| p.clone()).collect();
+        for path in unused_textures.iter() {
+            self.textures.remove(path);
+            // still watched while a font uses it as a page
+            if !self.font_pages.contains_key(path) {
+                self.watched.remove(path);
+            }
+        }
+        for path in unused_fonts.iter() {
+            self.fonts.remove(path);
+            self.watched.remove(path);
+            self.unwatch_font_pages(path);
+        }
+        unused_textures.len() + unused_fonts.len()
+    }
+}
+
+// the same file reached through different relative paths is loaded once
+pub fn normalize(path : &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or(path.to_path_buf())
+}
+
+fn load_texture(path : &Path, renderer : &Renderer) -> MotorResult<Texture> {
+    renderer.load_texture(path).map_err(|e| MotorError::TextureLoad { path : path.to_path_buf(), message : e })
+}
+
+fn get_modified(path : &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+// everything that can go wrong while starting up or loading assets
+#[derive(Debug)]
+pub enum MotorError {
+    SdlInit(String),
+    Window(String),
+    Renderer(String),
+    TextureCreate(String),
+    // a streaming texture couldn't be locked for writing
+    TextureLock(String),
+    // drawing into a texture failed or isn't supported by the renderer
+    RenderTarget(String),
+    Io { path : PathBuf, message : String },
+    TextureLoad { path : PathBuf, message : String },
+    // line numbers start at 1, formats without lines use 0
+    FontParse { path : PathBuf, line : usize, message : String },
+    // a glyph refers to a page the font file doesn't declare
+    MissingGlyphPage { path : PathBuf, page : u32 },
+    AtlasParse { path : PathBuf, message : String }
+}
+
+pub type MotorResult<T> = Result<T, MotorError>;
+
+impl fmt::Display for MotorError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MotorError::SdlInit(ref message) => write!(f, "failed to initialize SDL: {}", message),
+            MotorError::Window(ref message) => write!(f, "failed to create the window: {}", message),
+            MotorError::Renderer(ref message) => write!(f, "failed to set up the renderer: {}", message),
+            MotorError::TextureCreate(ref message) => write!(f, "failed to create a texture: {}", message),
+            MotorError::TextureLock(ref message) => write!(f, "failed to lock a texture: {}", message),
+            MotorError::RenderTarget(ref message) => write!(f, "failed to render to a texture: {}", message),
+            MotorError::Io { ref path, ref message } => write!(f, "{}: {}", path.display(), message),
+            MotorError::TextureLoad { ref path, ref message } => write!(f, "failed to load texture {}: {}", path.display(), message),
+            MotorError::FontParse { ref path, line, ref message } => {
+                if line > 0 {
+                    write!(f, "{}:{}: {}", path.display(), line, message)
+                } else {
+                    write!(f, "{}: {}", path.display(), message)
+                }
+            },
+            MotorError::MissingGlyphPage { ref path, page } => write!(f, "{}: missing glyph page {}", path.display(), page),
+            MotorError::AtlasParse { ref path, ref message } => write!(f, "{}: {}", path.display(), message)
+        }
+    }
+}
+
+impl Error for MotorError {
+    fn description(&self) -> &str {
+        match *self {
+            MotorError::SdlInit(_) => "failed to initialize SDL",
+            MotorError::Window(_) => "failed to create the window",
+            MotorError::Renderer(_) => "failed to set up the renderer",
+            MotorError::TextureCreate(_) => "failed to create a texture",
+            MotorError::TextureLock(_) => "failed to lock a texture",
+            MotorError::RenderTarget(_) => "failed to render to a texture",
+            MotorError::Io { .. } => "failed to read a file",
+            MotorError::TextureLoad { .. } => "failed to load a texture",
+            MotorError::FontParse { .. } => "failed to parse a font",
+            MotorError::MissingGlyphPage { .. } => "missing glyph page",
+            MotorError::AtlasParse { .. } => "failed to parse a texture atlas"
+        }
+    }
+}
@@ -1,58 +1,101 @@
-use std::io::{BufReader, BufRead};
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::fs::File;
+use std::str::FromStr;
 
 use std::collections::HashMap;
 use std::string::String;
 
-use sdl2::rect::Rect;
 use sdl2::render::{Texture, Renderer};
 use sdl2_image::LoadTexture;
 
+use motor::error::{MotorError, MotorResult};
+use motor::gfx::make_rect;
+use motor::xml;
+
 // http://www.angelcode.com/products/bmfont/doc/file_format.html
-const TAG_INFO : &'static str = "info";
+// the text, xml and binary formats are supported, the format is detected from the contents
 const TAG_COMMON : &'static str = "common";
 const TAG_PAGE : &'static str = "page";
-const TAG_CHARS : &'static str = "chars";
 const TAG_CHAR : &'static str = "char";
+const TAG_KERNING : &'static str = "kerning";
+
+// BMFont writes the glyph drawn for characters the font doesn't have with this id
+const INVALID_CHAR_ID : i64 = -1;
 
 pub struct BitmapFont {
-    texture : Texture,
+    pages : Vec<Texture>,
+    // the image file of every page, in page order
+    page_paths : Vec<PathBuf>,
+    // distance between the tops of two lines
     pub line_height : i32,
+    // distance from the top of a line to the baseline
+    pub base : i32,
     glyphs : HashMap<char, Glyph>,
-    empty_glyph : Glyph
+    kernings : HashMap<(char, char), i32>,
+    // drawn for characters the font doesn't have
+    fallback_glyph : Glyph
 }
 
+// the contents of a font file, before the pages are loaded
 struct BitmapFontData {
-    file_name : Option<String>,
     line_height : Option<i32>,
-    glyphs : HashMap<char, Glyph>
+    base : Option<i32>,
+    // page files by page id, relative to the font file
+    pages : Vec<Option<String>>,
+    glyphs : HashMap<char, Glyph>,
+    invalid_glyph : Option<Glyph>,
+    kernings : HashMap<(char, char), i32>
 }
 
 impl BitmapFontData {
     fn new() -> BitmapFontData {
         BitmapFontData {
-            file_name : None,
             line_height : None,
-            glyphs : HashMap::new()
+            base : None,
+            pages : Vec::new(),
+            glyphs : HashMap::new(),
+            invalid_glyph : None,
+            kernings : HashMap::new()
         }
     }
 
-    fn set_file_name(&mut self, file_name : String) {
-        println!("set_file_name {:?}", file_name);
-        self.file_name = Some(file_name);
+    fn set_page(&mut self, id : usize, file_name : String) {
+        if self.pages.len() <= id {
+            self.pages.resize(id + 1, None);
+        }
+        self.pages[id] = Some(file_name);
     }
 
-    fn set_line_height(&mut self, line_height : i32) {
-        self.line_height = Some(line_height);
+    fn add_glyph(&mut self, id : i64, glyph : Glyph) {
+        if id == INVALID_CHAR_ID {
+            self.invalid_glyph = Some(glyph);
+        } else {
+            match to_char(id) {
+                Some(c) => { self.glyphs.insert(c, glyph); },
+                None => {}
+            }
+        }
     }
 
-    fn add_glyph(&mut self, c : char, glyph : Glyph) {
-        self.glyphs.insert(c, glyph);
+    fn add_kerning(&mut self, first : i64, second : i64, amount : i32) {
+        match (to_char(first), to_char(second)) {
+            (Some(first), Some(second)) => { self.kernings.insert((first, second), amount); },
+            _ => {}
+        }
     }
 }
 
-#[derive(Debug)]
+// ids are unicode code points, invalid ones are ignored
+fn to_char(id : i64) -> Option<char> {
+    if id < 0 || id > ::std::u32::MAX as i64 {
+        None
+    } else {
+        ::std::char::from_u32(id as u32)
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Glyph {
     x : i32,
     y : i32,
@@ -60,122 +103,418 @@ struct Glyph {
     height : u32,
     x_offset : i32,
     y_offset : i32,
-    x_advance : i32
+    x_advance : i32,
+    page : usize
 }
 
 impl Glyph {
     pub fn is_empty(&self) -> bool {
         self.width == 0 || self.height == 0
     }
+
+    fn empty(x_advance : i32) -> Glyph {
+        Glyph {
+            x : 0,
+            y : 0,
+            width : 0,
+            height : 0,
+            x_offset : 0,
+            y_offset : 0,
+            x_advance : x_advance,
+            page : 0
+        }
+    }
+}
+
+// the tag and key=value pairs of one text line or xml element, values are parsed with the line number in the error
+struct Attributes<'a> {
+    path : &'a Path,
+    line : usize,
+    tag : &'a str,
+    pairs : HashMap<&'a str, &'a str>
+}
+
+impl<'a> Attributes<'a> {
+    // values may be quoted and then contain spaces, like face="Courier New"
+    fn from_line(path : &'a Path, line : usize, source : &'a str) -> Attributes<'a> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        let mut quoted = false;
+        for (index, c) in source.char_indices() {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            if c.is_whitespace() && !quoted {
+                match start {
+                    Some(s) => tokens.push(&source[s..index]),
+                    None => {}
+                }
+                start = None;
+            } else if start.is_none() {
+                start = Some(index);
+            }
+        }
+        match start {
+            Some(s) => tokens.push(&source[s..]),
+            None => {}
+        }
+
+        let tag = tokens.first().map_or("", |t| *t);
+        let pairs = tokens.iter().skip(1).map(|key_value| {
+                let equals_index = key_value.find('=').unwrap_or(key_value.len());
+                let pair = key_value.split_at(equals_index);
+                (pair.0, pair.1.trim_left_matches('=').trim_matches('\"'))
+            }
+        ).collect::<HashMap<&str, &str>>();
+
+        Attributes {
+            path : path,
+            line : line,
+            tag : tag,
+            pairs : pairs
+        }
+    }
+
+    // elements don't know their line
+    fn from_element(path : &'a Path, element : &'a xml::Element) -> Attributes<'a> {
+        Attributes {
+            path : path,
+            line : 0,
+            tag : &element.name,
+            pairs : element.attributes.iter().map(|(key, value)| (&key[..], &value[..])).collect()
+        }
+    }
+
+    fn get<T : FromStr>(&self, key : &str) -> MotorResult<T> {
+        let value = try!(self.pairs.get(key).ok_or_else(|| self.error(format!("{} without {}", self.tag, key))));
+        value.parse::<T>().map_err(|_| self.error(format!("invalid {} {:?}", key, value)))
+    }
+
+    fn get_or<T : FromStr>(&self, key : &str, default : T) -> MotorResult<T> {
+        if self.pairs.contains_key(key) {
+            self.get(key)
+        } else {
+            Ok(default)
+        }
+    }
+
+    fn error(&self, message : String) -> MotorError {
+        font_error(self.path, self.line, message)
+    }
+}
+
+fn font_error(path : &Path, line : usize, message : String) -> MotorError {
+    MotorError::FontParse { path : path.to_path_buf(), line : line, message : message }
+}
+
+// the text and xml formats use the same tags and attribute names
+fn read_tag(data : &mut BitmapFontData, attributes : &Attributes) -> MotorResult<()> {
+    if attributes.tag == TAG_COMMON {
+        data.line_height = Some(try!(attributes.get("lineHeight")));
+        data.base = Some(try!(attributes.get("base")));
+    } else if attributes.tag == TAG_PAGE {
+        let id : usize = try!(attributes.get("id"));
+        data.set_page(id, try!(attributes.get("file")));
+    } else if attributes.tag == TAG_CHAR {
+        let id : i64 = try!(attributes.get("id"));
+        data.add_glyph(id, Glyph {
+            x : try!(attributes.get("x")),
+            y : try!(attributes.get("y")),
+            width : try!(attributes.get("width")),
+            height : try!(attributes.get("height")),
+            x_offset : try!(attributes.get("xoffset")),
+            y_offset : try!(attributes.get("yoffset")),
+            x_advance : try!(attributes.get("xadvance")),
+            page : try!(attributes.get_or("page", 0))
+        });
+    } else if attributes.tag == TAG_KERNING {
+        data.add_kerning(try!(attributes.get("first")), try!(attributes.get("second")), try!(attributes.get("amount")));
+    }
+    // info only describes the style and chars and kernings only hold counts
+    Ok(())
+}
+
+fn parse_text(path : &Path, source : &str) -> MotorResult<BitmapFontData> {
+    let mut data = BitmapFontData::new();
+    let mut line_count = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        line_count = index + 1;
+        try!(read_tag(&mut data, &Attributes::from_line(path, index + 1, line)));
+    }
+
+    if data.line_height.is_none() {
+        return Err(font_error(path, line_count, "missing common line".to_string()));
+    }
+    Ok(data)
+}
+
+// <font><common/><pages><page/></pages><chars><char/></chars><kernings><kerning/></kernings></font>
+fn parse_xml(path : &Path, source : &str) -> MotorResult<BitmapFontData> {
+    let root = try!(xml::parse(source).map_err(|e| font_error(path, 0, e)));
+    if root.name != "font" {
+        return Err(font_error(path, 0, format!("expected font, found {}", root.name)));
+    }
+
+    let mut data = BitmapFontData::new();
+    for element in root.children.iter() {
+        match &element.name[..] {
+            "pages" | "chars" | "kernings" => {
+                for child in element.children.iter() {
+                    try!(read_tag(&mut data, &Attributes::from_element(path, child)));
+                }
+            },
+            _ => try!(read_tag(&mut data, &Attributes::from_element(path, element)))
+        }
+    }
+
+    if data.line_height.is_none() {
+        return Err(font_error(path, 0, "missing common element".to_string()));
+    }
+    Ok(data)
+}
+
+const BINARY_MAGIC : &'static [u8] = b"BMF";
+const BINARY_VERSION : u8 = 3;
+const BLOCK_INFO : u8 = 1;
+const BLOCK_COMMON : u8 = 2;
+const BLOCK_PAGES : u8 = 3;
+const BLOCK_CHARS : u8 = 4;
+const BLOCK_KERNINGS : u8 = 5;
+const CHAR_SIZE : usize = 20;
+const KERNING_SIZE : usize = 10;
+
+// reads the little endian values of the binary format
+struct BinaryReader<'a> {
+    path : &'a Path,
+    bytes : &'a [u8],
+    position : usize
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, count : usize) -> MotorResult<&'a [u8]> {
+        if self.bytes.len() - self.position < count {
+            return Err(font_error(self.path, 0, format!("unexpected end of file at byte {}", self.position)));
+        }
+        let bytes = &self.bytes[self.position..(self.position + count)];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> MotorResult<u8> {
+        Ok(try!(self.take(1))[0])
+    }
+
+    fn read_u16(&mut self) -> MotorResult<u16> {
+        let b = try!(self.take(2));
+        Ok(b[0] as u16 | (b[1] as u16) << 8)
+    }
+
+    fn read_i16(&mut self) -> MotorResult<i16> {
+        Ok(try!(self.read_u16()) as i16)
+    }
+
+    fn read_u32(&mut self) -> MotorResult<u32> {
+        let b = try!(self.take(4));
+        Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+}
+
+// ids are written as unsigned, the invalid char glyph is 0xffffffff
+fn binary_id(id : u32) -> i64 {
+    if id == ::std::u32::MAX { INVALID_CHAR_ID } else { id as i64 }
+}
+
+// version 3 of the binary format, a header followed by blocks of a type byte, a size and the data
+fn parse_binary(path : &Path, bytes : &[u8]) -> MotorResult<BitmapFontData> {
+    let mut reader = BinaryReader { path : path, bytes : bytes, position : 0 };
+    if try!(reader.take(BINARY_MAGIC.len())) != BINARY_MAGIC {
+        return Err(font_error(path, 0, "not a binary font".to_string()));
+    }
+    let version = try!(reader.read_u8());
+    if version != BINARY_VERSION {
+        return Err(font_error(path, 0, format!("unsupported binary version {}, only {} is supported", version, BINARY_VERSION)));
+    }
+
+    let mut data = BitmapFontData::new();
+    while !reader.is_at_end() {
+        let block_type = try!(reader.read_u8());
+        let block_size = try!(reader.read_u32()) as usize;
+        let block = try!(reader.take(block_size));
+        let mut block_reader = BinaryReader { path : path, bytes : block, position : 0 };
+
+        match block_type {
+            BLOCK_INFO => {},
+            BLOCK_COMMON => {
+                data.line_height = Some(try!(block_reader.read_u16()) as i32);
+                data.base = Some(try!(block_reader.read_u16()) as i32);
+            },
+            BLOCK_PAGES => {
+                // null terminated file names, one per page in id order
+                for (id, name) in block.split(|b| *b == 0).filter(|name| !name.is_empty()).enumerate() {
+                    let file_name = try!(String::from_utf8(name.to_vec()).map_err(|_| font_error(path, 0, format!("page {} has an invalid file name", id))));
+                    data.set_page(id, file_name);
+                }
+            },
+            BLOCK_CHARS => {
+                for _ in 0..(block_size / CHAR_SIZE) {
+                    let id = binary_id(try!(block_reader.read_u32()));
+                    let glyph = Glyph {
+                        x : try!(block_reader.read_u16()) as i32,
+                        y : try!(block_reader.read_u16()) as i32,
+                        width : try!(block_reader.read_u16()) as u32,
+                        height : try!(block_reader.read_u16()) as u32,
+                        x_offset : try!(block_reader.read_i16()) as i32,
+                        y_offset : try!(block_reader.read_i16()) as i32,
+                        x_advance : try!(block_reader.read_i16()) as i32,
+                        page : try!(block_reader.read_u8()) as usize
+                    };
+                    // the channel the glyph is in
+                    try!(block_reader.read_u8());
+                    data.add_glyph(id, glyph);
+                }
+            },
+            BLOCK_KERNINGS => {
+                for _ in 0..(block_size / KERNING_SIZE) {
+                    let first = binary_id(try!(block_reader.read_u32()));
+                    let second = binary_id(try!(block_reader.read_u32()));
+                    let amount = try!(block_reader.read_i16()) as i32;
+                    data.add_kerning(first, second, amount);
+                }
+            },
+            _ => return Err(font_error(path, 0, format!("unknown block type {}", block_type)))
+        }
+    }
+
+    if data.line_height.is_none() {
+        return Err(font_error(path, 0, "missing common block".to_string()));
+    }
+    Ok(data)
 }
 
 impl BitmapFont {
 
-    pub fn draw_str(&self, s : &'static str, x : i32, y: i32, renderer : &mut Renderer) {
+    pub fn draw_str(&self, s : &str, x : i32, y: i32, renderer : &mut Renderer) {
         let mut x_pos = x;
-        let y_pos = y;
+        let mut previous = None;
         for c in s.chars() {
-            match self.glyphs.get(&c) {
-                Some(glyph) => {
-                    if !glyph.is_empty() {
-                        renderer.copy(&self.texture,
-                            Some(Rect::new_unwrap(glyph.x, glyph.y, glyph.width, glyph.height)),
-                            Some(Rect::new_unwrap(x_pos + glyph.x_offset, y_pos + glyph.y_offset, glyph.width, glyph.height))
-                        );
-                    }
-                    x_pos += glyph.x_advance;
-                }
-                _ => {
-                    x_pos += self.empty_glyph.x_advance;
+            match previous {
+                Some(p) => x_pos += self.get_kerning(p, c),
+                None => {}
+            }
+            let glyph = self.get_glyph(c);
+            if !glyph.is_empty() {
+                match (make_rect(glyph.x, glyph.y, glyph.width, glyph.height),
+                       make_rect(x_pos + glyph.x_offset, y + glyph.y_offset, glyph.width, glyph.height)) {
+                    (Some(src), Some(dst)) => { renderer.copy(&self.pages[glyph.page], Some(src), Some(dst)); },
+                    _ => {}
                 }
             }
+            x_pos += glyph.x_advance;
+            previous = Some(c);
         }
     }
 
     pub fn draw_string(&self, s : String, x : i32, y: i32, renderer : &mut Renderer) {
-        let mut x_pos = x;
-        let y_pos = y;
+        self.draw_str(&s, x, y, renderer);
+    }
+
+    // draws with the baseline at y, so text in different fonts lines up
+    pub fn draw_on_baseline(&self, s : &str, x : i32, y : i32, renderer : &mut Renderer) {
+        self.draw_str(s, x, y - self.base, renderer);
+    }
+
+    // the horizontal distance draw_str moves for the string
+    pub fn get_width(&self, s : &str) -> i32 {
+        let mut width = 0;
+        let mut previous = None;
         for c in s.chars() {
-            match self.glyphs.get(&c) {
-                Some(glyph) => {
-                    if !glyph.is_empty() {
-                        renderer.copy(&self.texture,
-                            Some(Rect::new_unwrap(glyph.x, glyph.y, glyph.width, glyph.height)),
-                            Some(Rect::new_unwrap(x_pos + glyph.x_offset, y_pos + glyph.y_offset, glyph.width, glyph.height))
-                        );
-                    }
-                    x_pos += glyph.x_advance;
-                }
-                _ => {
-                    x_pos += self.empty_glyph.x_advance;
-                }
+            match previous {
+                Some(p) => width += self.get_kerning(p, c),
+                None => {}
             }
+            width += self.get_glyph(c).x_advance;
+            previous = Some(c);
         }
+        width
     }
 
-    pub fn load(font_file : &Path, renderer : &Renderer) -> Result<BitmapFont, &'static str> {
-        let mut data = BitmapFontData::new();
-
-        let reader = BufReader::new(File::open(&font_file).expect("Failed to load font file"));
-        for line in reader.lines() {
-            match line {
-                Ok(line) => {
-                    let pairs = line.split_whitespace().map(|key_value| {
-                            let equals_index = key_value.find('=').unwrap_or(key_value.len());
-                            let pair = key_value.split_at(equals_index);
-                            (pair.0, pair.1.trim_matches('=').trim_matches('\"'))
-                        }
-                    ).collect::<HashMap<&str, &str>>();
-
-                    if line.starts_with(TAG_INFO) {
-                        /*
-                        for (&key, &value) in pairs.iter() {
-                            println!("{}-{}", key, value);
-                        }
-                        */
-                    } else if line.starts_with(TAG_COMMON) {
-                        data.set_line_height(pairs.get("lineHeight").unwrap().parse::<i32>().unwrap());
-                    } else if line.starts_with(TAG_PAGE) {
-                        let file_name = pairs.get("file").unwrap();
-                        let p = Path::new(font_file.to_str().unwrap());
-                        data.set_file_name(p.parent().unwrap().join(file_name).to_str().unwrap().to_string());
-                    } else if line.starts_with(TAG_CHARS) {
-
-                    } else if line.starts_with(TAG_CHAR) {
-                        let c = pairs.get("id").unwrap().parse::<u8>().unwrap() as char;
-
-                        data.add_glyph(c, Glyph {
-                            x : pairs.get("x").unwrap().parse::<i32>().unwrap(),
-                            y : pairs.get("y").unwrap().parse::<i32>().unwrap(),
-                            width : pairs.get("width").unwrap().parse::<u32>().unwrap(),
-                            height : pairs.get("height").unwrap().parse::<u32>().unwrap(),
-                            x_offset : pairs.get("xoffset").unwrap().parse::<i32>().unwrap(),
-                            y_offset : pairs.get("yoffset").unwrap().parse::<i32>().unwrap(),
-                            x_advance : pairs.get("xadvance").unwrap().parse::<i32>().unwrap()
-                        });
-                    }
-                }
-                _ => {}
+    pub fn get_page_paths(&self) -> &[PathBuf] {
+        &self.page_paths
+    }
+
+    pub fn has_glyph(&self, c : char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+
+    fn get_glyph(&self, c : char) -> &Glyph {
+        self.glyphs.get(&c).unwrap_or(&self.fallback_glyph)
+    }
+
+    fn get_kerning(&self, first : char, second : char) -> i32 {
+        self.kernings.get(&(first, second)).map_or(0, |k| *k)
+    }
+
+    pub fn load(font_file : &Path, renderer : &Renderer) -> MotorResult<BitmapFont> {
+        let mut bytes = Vec::new();
+        try!(File::open(&font_file).and_then(|mut f| f.read_to_end(&mut bytes))
+            .map_err(|e| MotorError::Io { path : font_file.to_path_buf(), message : format!("{}", e) }));
+
+        // the binary format starts with BMF, xml with a tag and the text format with info
+        let data = if bytes.starts_with(BINARY_MAGIC) {
+            try!(parse_binary(font_file, &bytes))
+        } else {
+            let source = try!(String::from_utf8(bytes).map_err(|_| font_error(font_file, 0, "not a valid UTF-8 text file".to_string())));
+            let source = source.trim_left_matches('\u{feff}');
+            if source.trim_left().starts_with('<') {
+                try!(parse_xml(font_file, source))
+            } else {
+                try!(parse_text(font_file, source))
+            }
+        };
+        BitmapFont::from_data(font_file, data, renderer)
+    }
+
+    fn from_data(font_file : &Path, data : BitmapFontData, renderer : &Renderer) -> MotorResult<BitmapFont> {
+        // every page up to the highest id has to be there, glyphs refer to them by index
+        let directory = font_file.parent().unwrap_or(Path::new(""));
+        let mut pages = Vec::new();
+        let mut page_paths = Vec::new();
+        for (id, page) in data.pages.iter().enumerate() {
+            let file_name = try!(page.as_ref().ok_or_else(|| MotorError::MissingGlyphPage { path : font_file.to_path_buf(), page : id as u32 }));
+            let page_path : PathBuf = directory.join(file_name);
+            let texture = try!(renderer.load_texture(&page_path).map_err(|e| MotorError::TextureLoad { path : page_path.clone(), message : e }));
+            pages.push(texture);
+            page_paths.push(page_path);
+        }
+        for glyph in data.glyphs.values().chain(data.invalid_glyph.iter()) {
+            if glyph.page >= pages.len() {
+                return Err(MotorError::MissingGlyphPage { path : font_file.to_path_buf(), page : glyph.page as u32 });
             }
         }
 
-        let empty_glyph_width = data.glyphs.get(&'1').unwrap().x_advance;
-        let empty_glyph = Glyph {
-                x : 0,
-                y : 0,
-                width : 0,
-                height : 0,
-                x_offset : 0,
-                y_offset : 0,
-                x_advance : empty_glyph_width
-            };
+        let line_height = data.line_height.unwrap_or(0);
+        // without an invalid char glyph unknown characters are left blank, as wide as a space
+        let fallback_glyph = match data.invalid_glyph {
+            Some(glyph) => glyph,
+            None => {
+                let advance = data.glyphs.get(&' ').or(data.glyphs.get(&'1')).map_or(line_height / 2, |g| g.x_advance);
+                Glyph::empty(advance)
+            }
+        };
 
-        let s = data.file_name.unwrap();
         Ok(BitmapFont {
-            texture : renderer.load_texture(Path::new(&s)).unwrap(),
-            line_height : data.line_height.unwrap(),
+            pages : pages,
+            page_paths : page_paths,
+            line_height : line_height,
+            base : data.base.unwrap_or(line_height),
             glyphs : data.glyphs,
-            empty_glyph : empty_glyph
+            kernings : data.kernings,
+            fallback_glyph : fallback_glyph
         })
     }
 
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use rustc_serialize::json::Json;
+use sdl2::render::{Renderer, Texture};
+
+use motor::gfx::{Animation, PlayMode, TextureRegion};
+use motor::xml;
+use motor::assets::AssetManager;
+use motor::error::{MotorError, MotorResult};
+
+// used for frames that don't have a duration, TexturePacker doesn't export any
+const DEFAULT_FRAME_DURATION : f64 = 0.1f64;
+
+struct AtlasFrame {
+    name : String,
+    region : (u32, u32, u32, u32),
+    // in seconds
+    duration : Option<f64>
+}
+
+struct AtlasTag {
+    from : usize,
+    to : usize,
+    play_mode : PlayMode
+}
+
+// what a descriptor file contains, before the texture is loaded
+pub struct AtlasData {
+    image : String,
+    frames : Vec<AtlasFrame>,
+    tags : HashMap<String, AtlasTag>
+}
+
+// named regions and animations of one texture, loaded from a TexturePacker or Aseprite export.
+// supported are the JSON hash and array formats and the generic XML format
+pub struct TextureAtlas {
+    pub texture : Rc<RefCell<Texture>>,
+    data : AtlasData,
+    names : HashMap<String, usize>
+}
+
+impl TextureAtlas {
+    // the texture is shared with everything else that loads the same image
+    pub fn load(path : &Path, assets : &mut AssetManager, renderer : &Renderer) -> MotorResult<TextureAtlas> {
+        let mut source = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|e| MotorError::Io { path : path.to_path_buf(), message : format!("{}", e) }));
+
+        let is_xml = path.extension().map_or(false, |e| e == "xml");
+        let data = try!(if is_xml { parse_xml(&source) } else { parse_json(&source) }
+            .map_err(|e| MotorError::AtlasParse { path : path.to_path_buf(), message : e }));
+
+        // the image is relative to the descriptor
+        let image_path = path.parent().unwrap_or(Path::new("")).join(&data.image);
+        let texture = try!(assets.texture(&image_path, renderer));
+        Ok(TextureAtlas::new(texture, data))
+    }
+
+    pub fn new(texture : Rc<RefCell<Texture>>, data : AtlasData) -> TextureAtlas {
+        let names = data.frames.iter().enumerate().map(|(i, f)| (f.name.clone(), i)).collect();
+        TextureAtlas {
+            texture : texture,
+            data : data,
+            names : names
+        }
+    }
+
+    pub fn region(&self, name : &str) -> Option<TextureRegion> {
+        self.names.get(name).map(|&i| to_region(&self.data.frames[i]))
+    }
+
+    pub fn get_region_names(&self) -> Vec<&str> {
+        self.data.frames.iter().map(|f| &f.name[..]).collect()
+    }
+
+    // an Aseprite tag, or else the regions named like name_0, name_1, ... in number order
+    pub fn animation(&self, name : &str) -> Option<Animation> {
+        let frames : Vec<&AtlasFrame>;
+        let play_mode;
+        match self.data.tags.get(name) {
+            Some(tag) => {
+                if tag.from > tag.to || tag.to >= self.data.frames.len() {
+                    return None;
+                }
+                frames = self.data.frames[tag.from..(tag.to + 1)].iter().collect();
+                play_mode = tag.play_mode;
+            },
+            None => {
+                let mut numbered : Vec<(u32, &AtlasFrame)> = self.data.frames.iter()
+                    .filter_map(|f| frame_number(&f.name, name).map(|n| (n, f)))
+                    .collect();
+                numbered.sort_by(|a, b| a.0.cmp(&b.0));
+                frames = numbered.into_iter().map(|(_, f)| f).collect();
+                play_mode = PlayMode::Loop;
+            }
+        }
+        if frames.is_empty() {
+            return None;
+        }
+        let regions = frames.iter().map(|f| to_region(f)).collect();
+        let durations = frames.iter().map(|f| f.duration.unwrap_or(DEFAULT_FRAME_DURATION)).collect();
+        Some(Animation::with_durations(regions, durations).play_mode(play_mode))
+    }
+
+    pub fn get_animation_names(&self) -> Vec<&str> {
+        self.data.tags.keys().map(|k| &k[..]).collect()
+    }
+}
+
+fn to_region(frame : &AtlasFrame) -> TextureRegion {
+    let (x, y, w, h) = frame.region;
+    TextureRegion::new(x, y, w, h)
+}
+
+// the number of a frame named prefix + optional separator + number
+fn frame_number(name : &str, prefix : &str) -> Option<u32> {
+    if !name.starts_with(prefix) {
+        return None;
+    }
+    let rest = name[prefix.len()..].trim_left_matches(|c| c == '_' || c == '-' || c == ' ');
+    rest.parse::<u32>().ok()
+}
+
+// "walk_01.png" and "player 0.aseprite" are used as "walk_01" and "player 0"
+fn strip_extension(name : &str) -> String {
+    match name.rfind('.') {
+        Some(index) if index > 0 => name[..index].to_string(),
+        _ => name.to_string()
+    }
+}
+
+// sorts numbers by value so "walk 10" comes after "walk 9"
+fn natural_key(name : &str) -> (String, u32) {
+    let digits = name.len() - name.chars().rev().take_while(|c| c.is_digit(10)).count();
+    (name[..digits].to_string(), name[digits..].parse::<u32>().unwrap_or(0))
+}
+
+fn get_json_u32(json : &Json, name : &str) -> Result<u32, String> {
+    json.find(name).and_then(|v| v.as_u64()).map(|v| v as u32).ok_or(format!("missing {}", name))
+}
+
+fn read_json_frame(name : &str, json : &Json) -> Result<AtlasFrame, String> {
+    if json.find("rotated").and_then(|r| r.as_boolean()).unwrap_or(false) {
+        return Err(format!("frame {} is rotated, rotated frames are not supported", name));
+    }
+    let frame = try!(json.find("frame").ok_or(format!("frame {} has no rectangle", name)));
+    Ok(AtlasFrame {
+        name : strip_extension(name),
+        region : (try!(get_json_u32(frame, "x")), try!(get_json_u32(frame, "y")),
+                  try!(get_json_u32(frame, "w")), try!(get_json_u32(frame, "h"))),
+        duration : json.find("duration").and_then(|d| d.as_f64()).map(|ms| ms / 1000f64)
+    })
+}
+
+pub fn parse_json(source : &str) -> Result<AtlasData, String> {
+    let json = try!(Json::from_str(source).map_err(|e| format!("{}", e)));
+
+    let mut frames = Vec::new();
+    match json.find("frames") {
+        Some(&Json::Array(ref array)) => {
+            for frame in array.iter() {
+                let name = try!(frame.find("filename").and_then(|n| n.as_string()).ok_or("frame without filename".to_string()));
+                frames.push(try!(read_json_frame(name, frame)));
+            }
+        },
+        Some(&Json::Object(ref object)) => {
+            // objects lose the order of the file, Aseprite numbers its frames so that order is restored
+            for (name, frame) in object.iter() {
+                frames.push(try!(read_json_frame(name, frame)));
+            }
+            frames.sort_by(|a, b| natural_key(&a.name).cmp(&natural_key(&b.name)));
+        },
+        _ => return Err("missing frames".to_string())
+    }
+
+    let meta = try!(json.find("meta").ok_or("missing meta".to_string()));
+    let image = try!(meta.find("image").and_then(|i| i.as_string()).ok_or("missing meta.image".to_string()));
+
+    let mut tags = HashMap::new();
+    match meta.find("frameTags") {
+        Some(&Json::Array(ref array)) => {
+            for tag in array.iter() {
+                let name = try!(tag.find("name").and_then(|n| n.as_string()).ok_or("tag without name".to_string()));
+                let play_mode = match tag.find("direction").and_then(|d| d.as_string()) {
+                    Some("reverse") => PlayMode::Reversed,
+                    Some("pingpong") => PlayMode::PingPong,
+                    _ => PlayMode::Loop
+                };
+                tags.insert(name.to_string(), AtlasTag {
+                    from : try!(get_json_u32(tag, "from")) as usize,
+                    to : try!(get_json_u32(tag, "to")) as usize,
+                    play_mode : play_mode
+                });
+            }
+        },
+        _ => {}
+    }
+
+    Ok(AtlasData {
+        image : image.to_string(),
+        frames : frames,
+        tags : tags
+    })
+}
+
+fn get_xml_u32(element : &xml::Element, name : &str) -> Result<u32, String> {
+    element.get_attribute(name).and_then(|v| v.parse::<u32>().ok()).ok_or(format!("{} without a valid {}", element.name, name))
+}
+
+// <TextureAtlas imagePath="..."><SubTexture name="..." x="" y="" width="" height=""/></TextureAtlas>
+pub fn parse_xml(source : &str) -> Result<AtlasData, String> {
+    let root = try!(xml::parse(source));
+    if root.name != "TextureAtlas" {
+        return Err(format!("expected TextureAtlas, found {}", root.name));
+    }
+    let image = try!(root.get_attribute("imagePath").ok_or("missing imagePath".to_string()));
+
+    let mut frames = Vec::new();
+    for sub_texture in root.get_children("SubTexture") {
+        let name = try!(sub_texture.get_attribute("name").ok_or("SubTexture without name".to_string()));
+        if sub_texture.get_attribute("rotated") == Some("true") {
+            return Err(format!("frame {} is rotated, rotated frames are not supported", name));
+        }
+        frames.push(AtlasFrame {
+            name : strip_extension(name),
+            region : (try!(get_xml_u32(sub_texture, "x")), try!(get_xml_u32(sub_texture, "y")),
+                      try!(get_xml_u32(sub_texture, "width")), try!(get_xml_u32(sub_texture, "height"))),
+            duration : None
+        });
+    }
+
+    Ok(AtlasData {
+        image : image.to_string(),
+        frames : frames,
+        tags : HashMap::new()
+    })
+}
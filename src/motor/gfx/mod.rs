@@ -0,0 +1,603 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+pub mod atlas;
+pub mod sheet;
+
+use sdl2::rect::{Rect, Point};
+use sdl2::render::{Renderer, Texture, BlendMode};
+
+pub struct TextureRegion {
+    x : u32,
+    y : u32,
+    w : u32,
+    h : u32,
+    // None for empty regions, they aren't drawn
+    pub bounds : Option<Rect>
+}
+
+// Rect::new_unwrap panics on empty or oversized rects, this returns None for those instead
+pub fn make_rect(x : i32, y : i32, w : u32, h : u32) -> Option<Rect> {
+    match Rect::new(x, y, w, h) {
+        Ok(rect) => rect,
+        Err(_) => None
+    }
+}
+
+impl TextureRegion {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> TextureRegion {
+        let rect = make_rect(x as i32, y as i32, width, height);
+        TextureRegion {
+            x : x as u32,
+            y : y as u32,
+            w : width,
+            h : height,
+            bounds : rect
+        }
+    }
+
+    pub fn xywh(&self) -> (u32, u32, u32, u32) {
+        (self.x, self.y, self.w, self.h)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlayMode {
+    Loop,
+    // stops on the last frame
+    Once,
+    // forward then backward, without repeating the end frames
+    PingPong,
+    // loops from the last frame to the first
+    Reversed
+}
+
+pub struct Animation {
+    frames : Vec<TextureRegion>,
+    durations : Vec<f64>,
+    play_mode : PlayMode,
+    // frame indices in play order for one cycle
+    sequence : Vec<usize>,
+    cycle_duration : f64,
+    events : Vec<(usize, String)>
+}
+
+impl Animation {
+    pub fn new(frame_duration : f64, frames : Vec<TextureRegion>) -> Animation {
+        let durations = vec![frame_duration; frames.len()];
+        Animation::with_durations(frames, durations)
+    }
+
+    pub fn with_durations(frames : Vec<TextureRegion>, durations : Vec<f64>) -> Animation {
+        if frames.is_empty() || frames.len() != durations.len() {
+            panic!("an animation needs at least one frame and a duration for every frame");
+        }
+        let mut animation = Animation {
+            frames : frames,
+            durations : durations,
+            play_mode : PlayMode::Loop,
+            sequence : Vec::new(),
+            cycle_duration : 0f64,
+            events : Vec::new()
+        };
+        animation.set_play_mode(PlayMode::Loop);
+        animation
+    }
+
+    pub fn play_mode(mut self, play_mode : PlayMode) -> Animation {
+        self.set_play_mode(play_mode);
+        self
+    }
+
+    // the event fires whenever the animation enters the frame
+    pub fn event(mut self, frame : usize, name : &str) -> Animation {
+        self.events.push((frame, name.to_string()));
+        self
+    }
+
+    pub fn set_play_mode(&mut self, play_mode : PlayMode) {
+        let count = self.frames.len();
+        self.play_mode = play_mode;
+        self.sequence = match play_mode {
+            PlayMode::Loop | PlayMode::Once => (0..count).collect(),
+            PlayMode::Reversed => (0..count).rev().collect(),
+            PlayMode::PingPong => (0..count).chain((1..count.saturating_sub(1)).rev()).collect()
+        };
+        self.cycle_duration = self.sequence.iter().map(|&i| self.durations[i]).fold(0f64, |a, b| a + b);
+    }
+
+    pub fn get_play_mode(&self) -> PlayMode {
+        self.play_mode
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        self.cycle_duration
+    }
+
+    pub fn is_finished(&self, state_time : f64) -> bool {
+        self.play_mode == PlayMode::Once && state_time >= self.cycle_duration
+    }
+
+    // the number of frames entered since the start, counting repeats
+    fn get_step(&self, state_time : f64) -> usize {
+        if self.cycle_duration <= 0f64 || state_time <= 0f64 {
+            return 0;
+        }
+        let length = self.sequence.len();
+        if self.is_finished(state_time) {
+            return length - 1;
+        }
+        let cycles = (state_time / self.cycle_duration).floor();
+        let mut time = state_time - cycles * self.cycle_duration;
+        let mut position = 0;
+        while position < length - 1 && time >= self.durations[self.sequence[position]] {
+            time -= self.durations[self.sequence[position]];
+            position += 1;
+        }
+        cycles as usize * length + position
+    }
+
+    pub fn get_frame_index(&self, state_time : f64) -> usize {
+        self.sequence[self.get_step(state_time) % self.sequence.len()]
+    }
+
+    pub fn get_texture_region(&self, state_time : f64) -> &TextureRegion {
+        &self.frames[self.get_frame_index(state_time)]
+    }
+
+    // the events of the frames entered after from_time, up to and including to_time.
+    // the first frame is entered when from_time is at the start of the animation
+    pub fn get_events(&self, from_time : f64, to_time : f64) -> Vec<&str> {
+        let mut events = Vec::new();
+        if self.events.is_empty() || to_time <= from_time {
+            return events;
+        }
+        let first_step = if from_time <= 0f64 { 0 } else { self.get_step(from_time) + 1 };
+        for step in first_step..(self.get_step(to_time) + 1) {
+            let frame = self.sequence[step % self.sequence.len()];
+            for &(event_frame, ref name) in self.events.iter() {
+                if event_frame == frame {
+                    events.push(&name[..]);
+                }
+            }
+        }
+        events
+    }
+}
+
+// the name SpriteBuilder::animation and Sprite::set_animation store their animation under
+pub const DEFAULT_ANIMATION : &'static str = "default";
+
+// the named animations of a sprite, at most one of them is playing
+pub struct AnimationSet {
+    animations : HashMap<String, Animation>,
+    current : Option<String>
+}
+
+impl AnimationSet {
+    pub fn new() -> AnimationSet {
+        AnimationSet {
+            animations : HashMap::new(),
+            current : None
+        }
+    }
+
+    // the first animation added starts playing
+    pub fn add(mut self, name : &str, animation : Animation) -> AnimationSet {
+        self.insert(name, animation);
+        self
+    }
+
+    pub fn insert(&mut self, name : &str, animation : Animation) {
+        self.animations.insert(name.to_string(), animation);
+        if self.current.is_none() {
+            self.current = Some(name.to_string());
+        }
+    }
+
+    pub fn get(&self, name : &str) -> Option<&Animation> {
+        self.animations.get(name)
+    }
+
+    pub fn contains(&self, name : &str) -> bool {
+        self.animations.contains_key(name)
+    }
+
+    pub fn get_names(&self) -> Vec<&str> {
+        self.animations.keys().map(|k| &k[..]).collect()
+    }
+
+    pub fn get_current(&self) -> Option<&Animation> {
+        self.current.as_ref().and_then(|name| self.animations.get(name))
+    }
+
+    pub fn get_current_name(&self) -> Option<&str> {
+        self.current.as_ref().map(|name| &name[..])
+    }
+
+    fn set_current(&mut self, name : &str) -> bool {
+        if !self.animations.contains_key(name) {
+            return false;
+        }
+        self.current = Some(name.to_string());
+        true
+    }
+}
+
+// picks the animation of a sprite from the state of its owner
+pub struct AnimationStateMachine<S> {
+    animations : HashMap<S, String>,
+    // states whose animation has to finish before the state can change, like attacks
+    uninterruptible : HashSet<S>,
+    // the state to go to when the animation of a state has finished, like hurt back to idle
+    next : HashMap<S, S>,
+    current : Option<S>
+}
+
+impl<S> AnimationStateMachine<S> where S : Eq + Hash + Copy {
+    pub fn new() -> AnimationStateMachine<S> {
+        AnimationStateMachine {
+            animations : HashMap::new(),
+            uninterruptible : HashSet::new(),
+            next : HashMap::new(),
+            current : None
+        }
+    }
+
+    pub fn state(mut self, state : S, animation_name : &str) -> AnimationStateMachine<S> {
+        self.animations.insert(state, animation_name.to_string());
+        self
+    }
+
+    pub fn uninterruptible(mut self, state : S) -> AnimationStateMachine<S> {
+        self.uninterruptible.insert(state);
+        self
+    }
+
+    pub fn then(mut self, state : S, next_state : S) -> AnimationStateMachine<S> {
+        self.next.insert(state, next_state);
+        self
+    }
+
+    pub fn get_state(&self) -> Option<S> {
+        self.current
+    }
+
+    // asks for a state and plays its animation on the sprite, returns the state that is actually current
+    pub fn update(&mut self, sprite : &mut Sprite, requested : S) -> S {
+        let mut state = requested;
+        match self.current {
+            Some(current) => {
+                let finished = sprite.is_finished();
+                if !finished && self.uninterruptible.contains(&current) {
+                    return current;
+                }
+                if finished && state == current {
+                    state = self.next.get(&current).cloned().unwrap_or(state);
+                }
+                if state == current {
+                    return current;
+                }
+            },
+            None => {}
+        }
+        match self.animations.get(&state) {
+            Some(name) => {
+                sprite.play(name);
+            },
+            None => {}
+        }
+        self.current = Some(state);
+        state
+    }
+}
+
+pub struct SpriteBuilder {
+    texture : Rc<RefCell<Texture>>,
+    texture_region : Option<TextureRegion>,
+    animations : AnimationSet,
+    scale : (f64, f64),
+    origin : (f64, f64),
+    pivot : (f64, f64),
+    alpha : u8,
+    blend_mode : BlendMode
+}
+
+impl SpriteBuilder {
+    pub fn new(texture : Rc<RefCell<Texture>>) -> SpriteBuilder {
+        SpriteBuilder {
+            texture : texture,
+            texture_region : None,
+            animations : AnimationSet::new(),
+            scale : (1f64, 1f64),
+            origin : (0f64, 0f64),
+            pivot : (0.5f64, 0.5f64),
+            alpha : 255,
+            blend_mode : BlendMode::Blend
+        }
+    }
+    pub fn texture_region(mut self, texture_region : TextureRegion) -> SpriteBuilder {
+        self.texture_region = Some(texture_region);
+        self
+    }
+    pub fn animation(mut self, animation : Animation) -> SpriteBuilder {
+        self.animations.insert(DEFAULT_ANIMATION, animation);
+        self
+    }
+    pub fn named_animation(mut self, name : &str, animation : Animation) -> SpriteBuilder {
+        self.animations.insert(name, animation);
+        self
+    }
+    pub fn animations(mut self, animations : AnimationSet) -> SpriteBuilder {
+        self.animations = animations;
+        self
+    }
+    pub fn scale(mut self, scale_x : f64, scale_y : f64) -> SpriteBuilder {
+        self.scale = (scale_x, scale_y);
+        self
+    }
+    pub fn origin(mut self, origin_x : f64, origin_y : f64) -> SpriteBuilder {
+        self.origin = (origin_x, origin_y);
+        self
+    }
+    pub fn pivot(mut self, pivot_x : f64, pivot_y : f64) -> SpriteBuilder {
+        self.pivot = (pivot_x, pivot_y);
+        self
+    }
+    pub fn alpha(mut self, alpha : u8) -> SpriteBuilder {
+        self.alpha = alpha;
+        self
+    }
+    pub fn blend_mode(mut self, blend_mode : BlendMode) -> SpriteBuilder {
+        self.blend_mode = blend_mode;
+        self
+    }
+    pub fn build(self) -> Sprite {
+        if self.texture_region.is_none() && self.animations.get_current().is_none() {
+            panic!("a sprite needs a texture_region or an animation");
+        }
+        Sprite {
+            texture : self.texture,
+            texture_region : self.texture_region,
+            animations : self.animations,
+            state_time : 0f64,
+            events : Vec::new(),
+            color : (255, 255, 255),
+            flip_h : false,
+            flip_v : false,
+            rotation : 0f64,
+            scale : self.scale,
+            origin : self.origin,
+            pivot : self.pivot,
+            alpha : self.alpha,
+            blend_mode : self.blend_mode
+        }
+    }
+}
+
+// shows the playing animation, or the texture region when no animation is playing
+pub struct Sprite {
+    texture : Rc<RefCell<Texture>>,
+    texture_region : Option<TextureRegion>,
+    animations : AnimationSet,
+    state_time : f64,
+    events : Vec<String>,
+    pub color : (u8, u8, u8),
+    pub flip_h : bool,
+    pub flip_v : bool,
+    // clockwise, in degrees
+    pub rotation : f64,
+    pub scale : (f64, f64),
+    // the point of the sprite that is placed at the render position, as a fraction of its size
+    pub origin : (f64, f64),
+    // the point of the sprite it rotates around, as a fraction of its size
+    pub pivot : (f64, f64),
+    pub alpha : u8,
+    pub blend_mode : BlendMode
+}
+
+impl Sprite {
+    pub fn update(&mut self, delta_time : f64) {
+        let previous_time = self.state_time;
+        self.state_time += delta_time;
+
+        self.events.clear();
+        match self.animations.get_current() {
+            Some(animation) => {
+                for event in animation.get_events(previous_time, self.state_time) {
+                    self.events.push(event.to_string());
+                }
+            },
+            None => {}
+        }
+    }
+
+    // the animation events fired during the last update
+    pub fn get_events(&self) -> &[String] {
+        &self.events
+    }
+
+    // reset starts the new animation from its first frame, otherwise it continues at the current time
+    pub fn set_animation(&mut self, animation : Animation, reset : bool) {
+        self.animations.insert(DEFAULT_ANIMATION, animation);
+        self.animations.set_current(DEFAULT_ANIMATION);
+        if reset {
+            self.state_time = 0f64;
+        }
+    }
+
+    // switches to a named animation from its first frame, playing the current animation again does nothing.
+    // returns false if there is no animation with the name
+    pub fn play(&mut self, name : &str) -> bool {
+        if self.animations.get_current_name() == Some(name) {
+            return true;
+        }
+        if !self.animations.set_current(name) {
+            return false;
+        }
+        self.state_time = 0f64;
+        true
+    }
+
+    pub fn get_animations(&self) -> &AnimationSet {
+        &self.animations
+    }
+
+    pub fn get_animations_mut(&mut self) -> &mut AnimationSet {
+        &mut self.animations
+    }
+
+    pub fn get_state_time(&self) -> f64 {
+        self.state_time
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.animations.get_current().map_or(true, |a| a.is_finished(self.state_time))
+    }
+
+    pub fn render_at(&self, x : f64, y: f64, renderer : &mut Renderer) {
+        self.render_at_tinted(x, y, 1f64, (255, 255, 255), renderer);
+    }
+
+    pub fn get_texture(&self) -> &Rc<RefCell<Texture>> {
+        &self.texture
+    }
+
+    // the region of the current animation frame
+    pub fn get_texture_region(&self) -> &TextureRegion {
+        match self.animations.get_current() {
+            Some(animation) => animation.get_texture_region(self.state_time),
+            None => self.texture_region.as_ref().unwrap()
+        }
+    }
+
+    // the size on screen when rendered with the given scale
+    pub fn get_size(&self, scale : f64) -> (f64, f64) {
+        let texture_region = self.get_texture_region();
+        (texture_region.w as f64 * self.scale.0 * scale, texture_region.h as f64 * self.scale.1 * scale)
+    }
+
+    // the tint is multiplied with the sprite color, used for lighting
+    pub fn render_at_tinted(&self, x : f64, y: f64, scale : f64, tint : (u8, u8, u8), renderer : &mut Renderer) {
+        let mut t = self.texture.borrow_mut();
+        self.render_with(&mut t, x, y, scale, tint, renderer);
+    }
+
+    // renders with a texture that is already borrowed, it must be the texture of the sprite
+    pub fn render_with(&self, t : &mut Texture, x : f64, y: f64, scale : f64, tint : (u8, u8, u8), renderer : &mut Renderer) {
+        t.set_color_mod((self.color.0 as u32 * tint.0 as u32 / 255) as u8,
+                        (self.color.1 as u32 * tint.1 as u32 / 255) as u8,
+                        (self.color.2 as u32 * tint.2 as u32 / 255) as u8);
+        t.set_alpha_mod(self.alpha);
+        t.set_blend_mode(self.blend_mode);
+        let texture_region = self.get_texture_region();
+        let (w, h) = self.get_size(scale);
+        if w < 1f64 || h < 1f64 {
+            return;
+        }
+        let p = ((x - self.origin.0 * w).round() as i32, (y - self.origin.1 * h).round() as i32);
+        let size = (w.round() as u32, h.round() as u32);
+        if self.rotation == 0f64 && !self.flip_h && !self.flip_v {
+            render_region_dst(renderer, t, texture_region, p, size);
+        } else {
+            let pivot = ((self.pivot.0 * w).round() as i32, (self.pivot.1 * h).round() as i32);
+            render_region_ex(renderer, t, texture_region, p, size, self.rotation, pivot, (self.flip_h, self.flip_v));
+        }
+    }
+
+}
+
+pub struct NinePatch {
+    texture : Rc<RefCell<Texture>>,
+    top_left : TextureRegion,
+    top_center : TextureRegion,
+    top_right : TextureRegion,
+    middle_left : TextureRegion,
+    middle_center : TextureRegion,
+    middle_right : TextureRegion,
+    bottom_left : TextureRegion,
+    bottom_center : TextureRegion,
+    bottom_right : TextureRegion
+}
+
+impl NinePatch {
+    pub fn new(texture : Rc<RefCell<Texture>>, texture_region : TextureRegion, left : u32, right : u32, top : u32, bottom : u32) -> NinePatch {
+        let (x, y, w, h) = texture_region.xywh();
+        let left_width = x + left;
+        let center_width = w - left - right;
+        let middle_height = h - top - bottom;
+
+        let top_left = TextureRegion::new(x, y, left_width, top);
+        let top_center = TextureRegion::new(left_width, y, center_width, top);
+        let top_right = TextureRegion::new(x + w - right, y, right, top);
+        let middle_left = TextureRegion::new(x, y + top, left, middle_height);
+        let middle_center = TextureRegion::new(x + left, y + top, left, middle_height);
+        let middle_right = TextureRegion::new(x, y + top, right, middle_height);
+        let bottom_left = TextureRegion::new(x, y + h - bottom, left_width, bottom);
+        let bottom_center = TextureRegion::new(x + left, y + h - bottom, center_width, bottom);
+        let bottom_right = TextureRegion::new(x + w - right, y + h - bottom, right, bottom);
+
+        NinePatch {
+            texture : texture,
+            top_left : top_left,
+            top_center : top_center,
+            top_right : top_right,
+            middle_left : middle_left,
+            middle_center : middle_center,
+            middle_right : middle_right,
+            bottom_left : bottom_left,
+            bottom_center : bottom_center,
+            bottom_right : bottom_right
+        }
+    }
+
+    pub fn render(&self, (x, y, w, h) : (i32, i32, u32, u32), renderer : &mut Renderer) {
+        let t = self.texture.borrow();
+        let left_width = self.top_left.w;
+        let right_width = self.top_right.w;
+        let top_height = self.top_left.h;
+        let bottom_height = self.bottom_left.h;
+        let center_width = w - left_width - right_width;
+        let center_height = h - top_height - bottom_height;
+
+        render_region_dst(renderer, &t, &self.top_left, (x, y), (left_width, top_height));
+        render_region_dst(renderer, &t, &self.top_center, (x + left_width as i32, y), (center_width, top_height));
+        render_region_dst(renderer, &t, &self.top_right, (x + w as i32 - right_width as i32, y), (right_width, top_height));
+        render_region_dst(renderer, &t, &self.middle_left, (x, y + top_height as i32), (left_width, center_height));
+        render_region_dst(renderer, &t, &self.middle_center, (x + left_width as i32, y + top_height as i32), (center_width, center_height));
+        render_region_dst(renderer, &t, &self.middle_right, (x + w as i32 - right_width as i32, y + top_height as i32), (right_width, center_height));
+        render_region_dst(renderer, &t, &self.bottom_left, (x, y + h as i32 - bottom_height as i32), (left_width, bottom_height));
+        render_region_dst(renderer, &t, &self.bottom_center, (x + left_width as i32, y + h as i32 - bottom_height as i32), (center_width, bottom_height));
+        render_region_dst(renderer, &t, &self.bottom_right, (x + w as i32 - right_width as i32, y + h as i32 - bottom_height as i32), (right_width, bottom_height));
+    }
+}
+
+pub fn render_region_dst(renderer : &mut Renderer, texture: &Texture, texture_region : &TextureRegion, position : (i32, i32), size : (u32, u32)) {
+    match (texture_region.bounds, make_rect(position.0, position.1, size.0, size.1)) {
+        (Some(src), Some(dst)) => { renderer.copy(texture, Some(src), Some(dst)); },
+        _ => {}
+    }
+}
+
+// rotation in degrees around the pivot, which is relative to the position
+pub fn render_region_ex(renderer : &mut Renderer, texture: &Texture, texture_region : &TextureRegion, position : (i32, i32), size : (u32, u32), rotation : f64, pivot : (i32, i32), (flip_h, flip_v) : (bool, bool)) {
+    match (texture_region.bounds, make_rect(position.0, position.1, size.0, size.1)) {
+        (Some(src), Some(dst)) => {
+            renderer.copy_ex(texture,
+                Some(src),
+                Some(dst),
+                rotation,
+                Some(Point::new(pivot.0, pivot.1)),
+                flip_h,
+                flip_v
+            );
+        },
+        _ => {}
+    }
+}
+
+pub fn render_region(renderer : &mut Renderer, texture: &Texture, texture_region : &TextureRegion, position : (i32, i32)) {
+    render_region_dst(renderer, texture, texture_region, position, (texture_region.w, texture_region.h));
+}
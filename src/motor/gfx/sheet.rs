@@ -0,0 +1,97 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use sdl2::render::Texture;
+
+use motor::gfx::{Animation, TextureRegion};
+
+// a texture made of equally sized cells, numbered left to right and top to bottom
+pub struct SpriteSheet {
+    pub texture : Rc<RefCell<Texture>>,
+    cell_size : (u32, u32),
+    // empty pixels around the cells
+    margin : u32,
+    // empty pixels between the cells
+    spacing : u32,
+    columns : u32,
+    rows : u32
+}
+
+impl SpriteSheet {
+    pub fn new(texture : Rc<RefCell<Texture>>, cell_width : u32, cell_height : u32) -> SpriteSheet {
+        SpriteSheet::with_layout(texture, cell_width, cell_height, 0, 0)
+    }
+
+    pub fn with_layout(texture : Rc<RefCell<Texture>>, cell_width : u32, cell_height : u32, margin : u32, spacing : u32) -> SpriteSheet {
+        let query = texture.borrow().query();
+        let count = |size : u32, cell : u32| {
+            if size < margin * 2 + cell {
+                0
+            } else {
+                (size - margin * 2 + spacing) / (cell + spacing)
+            }
+        };
+        SpriteSheet {
+            columns : count(query.width, cell_width),
+            rows : count(query.height, cell_height),
+            texture : texture,
+            cell_size : (cell_width, cell_height),
+            margin : margin,
+            spacing : spacing
+        }
+    }
+
+    pub fn get_columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn get_rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn len(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    pub fn region(&self, column : u32, row : u32) -> Option<TextureRegion> {
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        let (w, h) = self.cell_size;
+        let x = self.margin + column * (w + self.spacing);
+        let y = self.margin + row * (h + self.spacing);
+        Some(TextureRegion::new(x, y, w, h))
+    }
+
+    pub fn region_at(&self, index : usize) -> Option<TextureRegion> {
+        if self.columns == 0 {
+            return None;
+        }
+        self.region(index as u32 % self.columns, index as u32 / self.columns)
+    }
+
+    // the cells from first to last, last included
+    pub fn animation(&self, first : usize, last : usize, frame_duration : f64) -> Option<Animation> {
+        let indices : Vec<usize> = if first <= last {
+            (first..(last + 1)).collect()
+        } else {
+            (last..(first + 1)).rev().collect()
+        };
+        self.animation_from(&indices, frame_duration)
+    }
+
+    // an animation of any cells, in the given order
+    pub fn animation_from(&self, indices : &[usize], frame_duration : f64) -> Option<Animation> {
+        let mut frames = Vec::new();
+        for &index in indices.iter() {
+            match self.region_at(index) {
+                Some(region) => frames.push(region),
+                None => return None
+            }
+        }
+        if frames.is_empty() {
+            return None;
+        }
+        Some(Animation::new(frame_duration, frames))
+    }
+}
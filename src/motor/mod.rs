@@ -6,19 +6,24 @@ pub mod joystick;
 pub mod mouse;
 pub mod gfx;
 pub mod font;
+pub mod xml;
+pub mod queue;
+pub mod assets;
+pub mod error;
 mod timer;
 
 use sdl2::{EventPump, GameControllerSubsystem};
 use sdl2::event::Event;
-use sdl2::render::{Renderer, Texture};
+use sdl2::render::{Renderer, Texture, BlendMode};
 use sdl2_image::{INIT_PNG, LoadTexture};
-use sdl2::rect::Rect;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 
 use std::path::Path;
 use std::rc::Rc;
 use std::cell::RefCell;
 
+pub use self::error::{MotorError, MotorResult};
+
 pub type TextureReference = Rc<RefCell<Texture>>;
 
 pub struct MotorContext<'window> {
@@ -27,6 +32,7 @@ pub struct MotorContext<'window> {
     pub keyboard : keyboard::MotorKeyboard,
     pub joystick : joystick::MotorJoystick,
     pub mouse : mouse::MotorMouse,
+    pub assets : assets::AssetManager,
     pub draw_debug_boxes : bool
 }
 
@@ -40,6 +46,7 @@ impl<'window> MotorContext<'window> {
             keyboard : keyboard::MotorKeyboard::new(),
             joystick : joystick::MotorJoystick::new(game_controller_subsystem),
             mouse : mouse::MotorMouse::new(),
+            assets : assets::AssetManager::new(),
             draw_debug_boxes : false
         }
     }
@@ -56,26 +63,85 @@ impl<'window> Drop for MotorContext<'window> {
 }
 
 pub trait MotorGraphics {
-    fn load_texture(&mut self, path : &Path) -> Texture;
-    fn load_texture_as_ref(&mut self, path : &Path) -> TextureReference;
-    fn load_font(&mut self, path : &Path) -> font::BitmapFont;
+    fn load_texture(&mut self, path : &Path) -> MotorResult<Texture>;
+    fn load_texture_as_ref(&mut self, path : &Path) -> MotorResult<TextureReference>;
+    fn load_font(&mut self, path : &Path) -> MotorResult<font::BitmapFont>;
+    // cached, loading the same file again returns the same texture
+    fn get_texture(&mut self, path : &Path) -> MotorResult<assets::TextureHandle>;
+    // cached, loading the same file again returns the same font
+    fn get_font(&mut self, path : &Path) -> MotorResult<assets::FontHandle>;
+    fn load_atlas(&mut self, path : &Path) -> MotorResult<gfx::atlas::TextureAtlas>;
+    // an RGB24 texture that can be written to with Texture::with_lock
+    fn create_streaming_texture(&mut self, w : u32, h : u32) -> MotorResult<Texture>;
+    // a transparent RGBA texture that can be drawn into with render_to_texture
+    fn create_target_texture(&mut self, w : u32, h : u32) -> MotorResult<Texture>;
+    // clears the texture, runs draw with the texture as render target and hands the texture back
+    fn render_to_texture<F>(&mut self, texture : Texture, draw : F) -> MotorResult<Texture> where F : FnOnce(&mut Self);
     fn render(&mut self, texture: &sdl2::render::Texture, texture_region : &gfx::TextureRegion, position : (i32, i32));
     fn render_sprite_at(&mut self, sprite : &gfx::Sprite, x : f64, y : f64) ;
+    fn render_scaled(&mut self, texture: &sdl2::render::Texture, texture_region : &gfx::TextureRegion, position : (i32, i32), size : (u32, u32));
+    fn render_sprite_tinted(&mut self, sprite : &gfx::Sprite, x : f64, y : f64, scale : f64, tint : (u8, u8, u8));
     fn render_nine_patch(&mut self, nine_patch : &gfx::NinePatch, x: i32, y : i32, w: u32, h : u32);
+    fn render_queue(&mut self, queue : &mut queue::RenderQueue);
     fn draw_rect(&mut self, x : f64, y : f64, w : f64, h: f64);
+    fn fill_rect(&mut self, x : i32, y : i32, w : u32, h : u32, color : (u8, u8, u8));
+    // limits rendering to a part of the screen, None renders everywhere again
+    fn set_clip_rect(&mut self, rect : Option<(i32, i32, u32, u32)>);
 }
 
 impl<'window> MotorGraphics for MotorContext<'window> {
-    fn load_texture(&mut self, path : &Path) -> Texture {
-        self.renderer.load_texture(path).unwrap()
+    fn load_texture(&mut self, path : &Path) -> MotorResult<Texture> {
+        self.renderer.load_texture(path).map_err(|e| MotorError::TextureLoad { path : path.to_path_buf(), message : e })
+    }
+
+    fn load_texture_as_ref(&mut self, path : &Path) -> MotorResult<TextureReference> {
+        self.get_texture(path)
+    }
+
+    fn load_font(&mut self, path: &Path) -> MotorResult<font::BitmapFont> {
+        font::BitmapFont::load(path, &self.renderer)
+    }
+
+    fn get_texture(&mut self, path : &Path) -> MotorResult<assets::TextureHandle> {
+        self.assets.texture(path, &self.renderer)
+    }
+
+    fn get_font(&mut self, path : &Path) -> MotorResult<assets::FontHandle> {
+        self.assets.font(path, &self.renderer)
+    }
+
+    fn load_atlas(&mut self, path : &Path) -> MotorResult<gfx::atlas::TextureAtlas> {
+        gfx::atlas::TextureAtlas::load(path, &mut self.assets, &self.renderer)
     }
 
-    fn load_texture_as_ref(&mut self, path : &Path) -> TextureReference {
-        Rc::new(RefCell::new(self.load_texture(path)))
+    fn create_streaming_texture(&mut self, w : u32, h : u32) -> MotorResult<Texture> {
+        self.renderer.create_texture_streaming(PixelFormatEnum::RGB24, (w, h)).map_err(|e| MotorError::TextureCreate(format!("{}", e)))
     }
 
-    fn load_font(&mut self, path: &Path) -> font::BitmapFont {
-        font::BitmapFont::load(path, &self.renderer).unwrap()
+    fn create_target_texture(&mut self, w : u32, h : u32) -> MotorResult<Texture> {
+        let mut texture = try!(self.renderer.create_texture_target(PixelFormatEnum::RGBA8888, (w, h)).map_err(|e| MotorError::TextureCreate(format!("{}", e))));
+        texture.set_blend_mode(BlendMode::Blend);
+        Ok(texture)
+    }
+
+    fn render_to_texture<F>(&mut self, texture : Texture, draw : F) -> MotorResult<Texture> where F : FnOnce(&mut Self) {
+        {
+            let mut target = try!(self.renderer.render_target().ok_or(MotorError::RenderTarget(format!("render targets are not supported"))));
+            try!(target.set(texture).map_err(|e| MotorError::RenderTarget(format!("{}", e))));
+        }
+
+        let previous = self.renderer.draw_color();
+        self.renderer.set_draw_color(Color::RGBA(0, 0, 0, 0));
+        self.renderer.clear();
+        self.renderer.set_draw_color(previous);
+
+        draw(self);
+
+        let mut target = try!(self.renderer.render_target().ok_or(MotorError::RenderTarget(format!("render targets are not supported"))));
+        match try!(target.reset().map_err(|e| MotorError::RenderTarget(format!("{}", e)))) {
+            Some(texture) => Ok(texture),
+            None => Err(MotorError::RenderTarget(format!("the target texture was lost")))
+        }
     }
 
     fn render(&mut self, texture: &sdl2::render::Texture, texture_region : &gfx::TextureRegion, position : (i32, i32)) {
@@ -86,50 +152,82 @@ impl<'window> MotorGraphics for MotorContext<'window> {
         sprite.render_at(x, y, &mut self.renderer);
     }
 
+    fn render_scaled(&mut self, texture: &sdl2::render::Texture, texture_region : &gfx::TextureRegion, position : (i32, i32), size : (u32, u32)) {
+        gfx::render_region_dst(&mut self.renderer, texture, texture_region, position, size);
+    }
+
+    fn render_sprite_tinted(&mut self, sprite : &gfx::Sprite, x : f64, y : f64, scale : f64, tint : (u8, u8, u8)) {
+        sprite.render_at_tinted(x, y, scale, tint, &mut self.renderer);
+    }
+
     fn render_nine_patch(&mut self, nine_patch : &gfx::NinePatch, x: i32, y : i32, w: u32, h : u32) {
         nine_patch.render((x, y, w, h), &mut self.renderer);
     }
 
+    fn render_queue(&mut self, queue : &mut queue::RenderQueue) {
+        queue.flush(&mut self.renderer);
+    }
+
     fn draw_rect(&mut self, x : f64, y : f64, w : f64, h: f64) {
         self.renderer.set_draw_color(Color::RGB(255, 0, 255)) ;
-        let rect = Rect::new_unwrap(x as i32, y as i32, w as u32, h as u32);
-        self.renderer.draw_rect(rect);
+        match gfx::make_rect(x as i32, y as i32, w as u32, h as u32) {
+            Some(rect) => { self.renderer.draw_rect(rect); },
+            None => {}
+        }
+    }
+
+    fn fill_rect(&mut self, x : i32, y : i32, w : u32, h : u32, color : (u8, u8, u8)) {
+        // the draw color is also the clear color, so put it back
+        let previous = self.renderer.draw_color();
+        self.renderer.set_draw_color(Color::RGB(color.0, color.1, color.2));
+        match gfx::make_rect(x, y, w, h) {
+            Some(rect) => { self.renderer.fill_rect(rect); },
+            None => {}
+        }
+        self.renderer.set_draw_color(previous);
+    }
+
+    fn set_clip_rect(&mut self, rect : Option<(i32, i32, u32, u32)>) {
+        // SDL can't clip to an empty rect, those turn clipping off
+        let clip = rect.and_then(|(x, y, w, h)| gfx::make_rect(x, y, w, h));
+        self.renderer.set_clip_rect(clip);
     }
 }
 
 pub trait MotorApp {
-    fn init(&mut self, context : &mut MotorContext);
+    // an error stops motor_start and is returned from it
+    fn init(&mut self, context : &mut MotorContext) -> MotorResult<()>;
     fn update(&mut self, context : &mut MotorContext, delta_time : f64) -> bool;
 }
 
-pub fn motor_start(window_title : &'static str, window_size : (u32, u32), logical_size : Option<(u32, u32)>, app : &mut MotorApp) {
-    let sdl_context = sdl2::init().unwrap();
-    let video = sdl_context.video().unwrap();
+pub fn motor_start(window_title : &'static str, window_size : (u32, u32), logical_size : Option<(u32, u32)>, app : &mut MotorApp) -> MotorResult<()> {
+    let sdl_context = try!(sdl2::init().map_err(|e| MotorError::SdlInit(format!("{}", e))));
+    let video = try!(sdl_context.video().map_err(|e| MotorError::SdlInit(format!("{}", e))));
 
-    let mut timer = timer::MotorTimer::new(60, sdl_context.timer().unwrap());
+    let mut timer = timer::MotorTimer::new(60, try!(sdl_context.timer().map_err(|e| MotorError::SdlInit(format!("{}", e)))));
     timer.set_enable_fps_log(false);
 
-    let window = video.window(window_title, window_size.0, window_size.1)
+    let window = try!(video.window(window_title, window_size.0, window_size.1)
         .position_centered()
         //.borderless()
         .opengl()
         .build()
-        .unwrap();
+        .map_err(|e| MotorError::Window(format!("{}", e))));
 
     let mut context = MotorContext::new(
-        window.renderer().build().unwrap(),
-        sdl_context.event_pump().unwrap(),
-        sdl_context.game_controller().unwrap()
+        try!(window.renderer().build().map_err(|e| MotorError::Renderer(format!("{}", e)))),
+        try!(sdl_context.event_pump().map_err(|e| MotorError::SdlInit(format!("{}", e)))),
+        try!(sdl_context.game_controller().map_err(|e| MotorError::SdlInit(format!("{}", e))))
     );
 
     match logical_size {
         Some((w, h)) => {
-            context.renderer.set_logical_size(w, h).unwrap();
+            try!(context.renderer.set_logical_size(w, h).map_err(|e| MotorError::Renderer(format!("{}", e))));
         }
         _ => {}
     }
 
-    app.init(&mut context);
+    try!(app.init(&mut context));
 
     'running: loop {
         context.update();
@@ -163,4 +261,6 @@ pub fn motor_start(window_title : &'static str, window_size : (u32, u32), logica
             context.renderer.present();
         }
     }
+
+    Ok(())
 }
@@ -0,0 +1,100 @@
+use std::cmp::Ordering;
+use std::cell::RefCell;
+
+use sdl2::render::{Renderer, Texture};
+
+use motor::gfx::Sprite;
+
+struct QueuedSprite<'a> {
+    sprite : &'a Sprite,
+    z : i32,
+    x : f64,
+    y : f64,
+    // the bottom edge on screen, sprites lower on the screen are drawn over the ones above them
+    bottom : f64,
+    scale : f64,
+    tint : (u8, u8, u8),
+    order : usize
+}
+
+impl<'a> QueuedSprite<'a> {
+    fn texture_id(&self) -> usize {
+        &**self.sprite.get_texture() as *const RefCell<Texture> as usize
+    }
+}
+
+const DEFAULT_Y_SORT_STEP : f64 = 2f64;
+
+// collects sprite draws for a frame and renders them back to front.
+// sprites are ordered by z, then by their bottom edge when y_sort is set, then grouped by texture
+// so every texture is only borrowed once per run of sprites using it
+pub struct RenderQueue<'a> {
+    pub y_sort : bool,
+    // bottom edges within the same step of this many pixels count as equal, so their sprites can be
+    // grouped by texture. 1 sorts exactly on whole pixels
+    pub y_sort_step : f64,
+    sprites : Vec<QueuedSprite<'a>>
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new(y_sort : bool) -> RenderQueue<'a> {
+        RenderQueue {
+            y_sort : y_sort,
+            y_sort_step : DEFAULT_Y_SORT_STEP,
+            sprites : Vec::new()
+        }
+    }
+
+    pub fn push(&mut self, sprite : &'a Sprite, z : i32, x : f64, y : f64, scale : f64, tint : (u8, u8, u8)) {
+        let order = self.sprites.len();
+        let (_, h) = sprite.get_size(scale);
+        self.sprites.push(QueuedSprite {
+            sprite : sprite,
+            z : z,
+            x : x,
+            y : y,
+            bottom : y - sprite.origin.1 * h + h,
+            scale : scale,
+            tint : tint,
+            order : order
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    // renders and empties the queue
+    pub fn flush(&mut self, renderer : &mut Renderer) {
+        let y_sort = self.y_sort;
+        let step = self.y_sort_step.max(1f64);
+        self.sprites.sort_by(|a, b| {
+            let mut ordering = a.z.cmp(&b.z);
+            if ordering == Ordering::Equal && y_sort {
+                let a_row = (a.bottom / step).floor();
+                let b_row = (b.bottom / step).floor();
+                ordering = a_row.partial_cmp(&b_row).unwrap_or(Ordering::Equal);
+            }
+            if ordering == Ordering::Equal {
+                ordering = a.texture_id().cmp(&b.texture_id());
+            }
+            if ordering == Ordering::Equal {
+                ordering = a.order.cmp(&b.order);
+            }
+            ordering
+        });
+
+        let mut start = 0;
+        while start < self.sprites.len() {
+            let texture_id = self.sprites[start].texture_id();
+            let end = self.sprites[start..].iter().position(|s| s.texture_id() != texture_id).map_or(self.sprites.len(), |i| start + i);
+
+            let mut texture = self.sprites[start].sprite.get_texture().borrow_mut();
+            for queued in self.sprites[start..end].iter() {
+                queued.sprite.render_with(&mut texture, queued.x, queued.y, queued.scale, queued.tint, renderer);
+            }
+            start = end;
+        }
+        self.sprites.clear();
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// a minimal xml reader, enough for the files produced by tools like Tiled and BMFont.
+// no namespaces, no dtd, just elements, attributes and text.
+
+// longer references can't be valid, stops a stray '&' from swallowing the rest of the file
+const MAX_REFERENCE_LENGTH : usize = 16;
+
+#[derive(Debug)]
+pub struct Element {
+    pub name : String,
+    pub attributes : HashMap<String, String>,
+    pub children : Vec<Element>,
+    pub text : String
+}
+
+impl Element {
+    fn new(name : String) -> Element {
+        Element {
+            name : name,
+            attributes : HashMap::new(),
+            children : Vec::new(),
+            text : String::new()
+        }
+    }
+
+    pub fn get_attribute(&self, name : &str) -> Option<&str> {
+        self.attributes.get(name).map(|value| &value[..])
+    }
+
+    pub fn get_child(&self, name : &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn get_children(&self, name : &str) -> Vec<&Element> {
+        self.children.iter().filter(|c| c.name == name).collect()
+    }
+}
+
+struct Parser<'a> {
+    chars : Peekable<Chars<'a>>,
+    line : usize
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|c| *c)
+    }
+
+    fn error(&self, message : &str) -> String {
+        format!("xml line {}: {}", self.line, message)
+    }
+
+    fn expect(&mut self, expected : char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}' but found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}' but found end of file", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, |c| c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    // skips everything up to and including the terminator
+    fn skip_until(&mut self, terminator : &str) -> Result<(), String> {
+        let mut matched = String::new();
+        while !matched.ends_with(terminator) {
+            match self.next() {
+                Some(c) => matched.push(c),
+                None => return Err(self.error(&format!("missing '{}'", terminator)))
+            }
+        }
+        Ok(())
+    }
+
+    fn read_name(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+        while self.peek().map_or(false, |c| c.is_alphanumeric() || c == '_' || c == '-' || c == ':' || c == '.') {
+            name.push(self.next().unwrap());
+        }
+        if name.is_empty() {
+            return Err(self.error("expected a name"));
+        }
+        Ok(name)
+    }
+
+    fn read_attribute_value(&mut self) -> Result<String, String> {
+        let quote = match self.next() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return Err(self.error("expected a quoted attribute value"))
+        };
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => break,
+                Some('&') => value.push(try!(self.read_reference())),
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated attribute value"))
+            }
+        }
+        Ok(value)
+    }
+
+    // the part of an entity or character reference after the '&'
+    fn read_reference(&mut self) -> Result<char, String> {
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some(';') => break,
+                Some(c) if name.len() < MAX_REFERENCE_LENGTH => name.push(c),
+                _ => return Err(self.error("unterminated reference"))
+            }
+        }
+        let c = match &name[..] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "amp" => Some('&'),
+            _ if name.starts_with("#x") => u32::from_str_radix(&name[2..], 16).ok().and_then(::std::char::from_u32),
+            _ if name.starts_with('#') => name[1..].parse::<u32>().ok().and_then(::std::char::from_u32),
+            _ => None
+        };
+        c.ok_or_else(|| self.error(&format!("unknown reference '&{};'", name)))
+    }
+
+    // skips declarations, comments and doctypes between elements
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('<') {
+                return Ok(());
+            }
+            let mut lookahead = self.chars.clone();
+            lookahead.next();
+            match lookahead.next() {
+                Some('?') => try!(self.skip_until("?>")),
+                Some('!') => {
+                    if lookahead.next() == Some('-') {
+                        try!(self.skip_until("-->"));
+                    } else {
+                        try!(self.skip_until(">"));
+                    }
+                },
+                _ => return Ok(())
+            }
+        }
+    }
+
+    fn read_element(&mut self) -> Result<Element, String> {
+        try!(self.expect('<'));
+        let mut element = Element::new(try!(self.read_name()));
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('/') => {
+                    self.next();
+                    try!(self.expect('>'));
+                    return Ok(element);
+                },
+                Some('>') => {
+                    self.next();
+                    break;
+                },
+                Some(_) => {
+                    let name = try!(self.read_name());
+                    self.skip_whitespace();
+                    try!(self.expect('='));
+                    self.skip_whitespace();
+                    let value = try!(self.read_attribute_value());
+                    element.attributes.insert(name, value);
+                },
+                None => return Err(self.error("unterminated element"))
+            }
+        }
+
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                Some('<') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    match lookahead.next() {
+                        Some('/') => {
+                            self.next();
+                            self.next();
+                            let name = try!(self.read_name());
+                            if name != element.name {
+                                return Err(self.error(&format!("expected </{}> but found </{}>", element.name, name)));
+                            }
+                            self.skip_whitespace();
+                            try!(self.expect('>'));
+                            element.text = text.trim().to_string();
+                            return Ok(element);
+                        },
+                        Some('!') if lookahead.clone().take(7).collect::<String>() == "[CDATA[" => {
+                            for _ in 0..9 {
+                                self.next();
+                            }
+                            let mut data = String::new();
+                            while !data.ends_with("]]>") {
+                                match self.next() {
+                                    Some(c) => data.push(c),
+                                    None => return Err(self.error("unterminated CDATA section"))
+                                }
+                            }
+                            let length = data.len() - 3;
+                            text.push_str(&data[..length]);
+                        },
+                        Some('!') | Some('?') => try!(self.skip_misc()),
+                        _ => {
+                            let child = try!(self.read_element());
+                            element.children.push(child);
+                        }
+                    }
+                },
+                Some('&') => {
+                    self.next();
+                    text.push(try!(self.read_reference()));
+                },
+                Some(_) => text.push(self.next().unwrap()),
+                None => return Err(self.error(&format!("missing </{}>", element.name)))
+            }
+        }
+    }
+}
+
+pub fn parse(source : &str) -> Result<Element, String> {
+    let mut parser = Parser {
+        chars : source.chars().peekable(),
+        line : 1
+    };
+    try!(parser.skip_misc());
+    parser.read_element()
+}
+
+pub fn escape(s : &str) -> String {
+    s.replace("&", "&amp;")
+     .replace("<", "&lt;")
+     .replace(">", "&gt;")
+     .replace("\"", "&quot;")
+     .replace("'", "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_attributes_and_children() {
+        let root = parse("<map width=\"10\" name='a b'><layer id=\"1\"/><layer id=\"2\"></layer></map>").unwrap();
+        assert_eq!(root.name, "map");
+        assert_eq!(root.get_attribute("width"), Some("10"));
+        assert_eq!(root.get_attribute("name"), Some("a b"));
+        assert_eq!(root.get_attribute("height"), None);
+        let ids : Vec<&str> = root.get_children("layer").iter().map(|l| l.get_attribute("id").unwrap()).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn decodes_entities_and_character_references() {
+        let root = parse("<a title=\"&lt;&amp;&gt; &quot;&apos;\">&amp;lt; &#65;&#x42;&#x263a;</a>").unwrap();
+        assert_eq!(root.get_attribute("title"), Some("<&> \"'"));
+        assert_eq!(root.text, "&lt; AB\u{263a}");
+        assert!(parse("<a>&nope;</a>").is_err());
+        assert!(parse("<a>&amp</a>").is_err());
+    }
+
+    #[test]
+    fn keeps_cdata_as_is() {
+        let root = parse("<data><![CDATA[&amp; <b>]]>&amp;</data>").unwrap();
+        assert_eq!(root.text, "&amp; <b>&");
+        assert!(root.children.is_empty());
+    }
+
+    #[test]
+    fn skips_comments_and_the_xml_header() {
+        let source = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- made by hand -->\n<font>\n <!-- <page/> -->\n <page id=\"0\"/>\n</font>\n";
+        let root = parse(source).unwrap();
+        assert_eq!(root.name, "font");
+        assert_eq!(root.children.len(), 1);
+        assert_eq!(root.children[0].get_attribute("id"), Some("0"));
+    }
+
+    #[test]
+    fn escape_round_trips() {
+        let value = "<a & 'b' \"c\">";
+        let root = parse(&format!("<x v=\"{}\">{}</x>", escape(value), escape(value))).unwrap();
+        assert_eq!(root.get_attribute("v"), Some(value));
+        assert_eq!(root.text, value);
+    }
+
+    #[test]
+    fn reports_mismatched_tags() {
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a>").is_err());
+    }
+}
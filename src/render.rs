@@ -1,81 +1,366 @@
-use motor::{MotorContext, MotorGraphics};
+use motor::{MotorContext, MotorGraphics, MotorError, MotorResult};
 use motor::gfx::TextureRegion;
 use camera::Camera;
-use world::{Actor, World, Tile};
-use sdl2::render::{Texture};
+use world::{Actor, World, Tile, Cell};
+use world::grid::Grid;
+use world::tilemap::{Layer, TileMap, TileChanges};
+use world::fov::{FieldOfView, Visibility};
+use world::light::{LightMap, multiply_color};
+use motor::TextureReference;
+use motor::queue::RenderQueue;
+use sdl2::render::{Texture, BlendMode};
 use std::collections::HashMap;
 use std::cmp::*;
 
 
+struct TileVariant {
+    texture_region : TextureRegion,
+    weight : u32
+}
+
 pub struct TileSet {
-    pub texture : Texture,
-    tiles : HashMap<Tile, TextureRegion>
+    pub texture : TextureReference,
+    tiles : HashMap<Tile, Vec<TileVariant>>,
+    decorations : HashMap<Tile, Vec<TileVariant>>,
+    decoration_chance : u32
 }
 
 impl TileSet {
-    pub fn new(texture : Texture) -> TileSet {
+    pub fn new(texture : TextureReference) -> TileSet {
         TileSet {
             texture : texture,
-            tiles : HashMap::new()
+            tiles : HashMap::new(),
+            decorations : HashMap::new(),
+            decoration_chance : 0
         }
     }
 
     pub fn add_tile(&mut self, tile: Tile, texture_region : TextureRegion) {
-        self.tiles.insert(tile, texture_region);
+        self.add_tile_variant(tile, texture_region, 1);
+    }
+
+    pub fn add_tile_variant(&mut self, tile: Tile, texture_region : TextureRegion, weight : u32) {
+        self.tiles.entry(tile).or_insert(Vec::new()).push(TileVariant {
+            texture_region : texture_region,
+            weight : weight
+        });
+    }
+
+    // decorations are drawn on top of the tile and never affect collision
+    pub fn add_decoration(&mut self, tile: Tile, texture_region : TextureRegion, weight : u32) {
+        self.decorations.entry(tile).or_insert(Vec::new()).push(TileVariant {
+            texture_region : texture_region,
+            weight : weight
+        });
+    }
+
+    // chance in percent that a cell gets a decoration
+    pub fn set_decoration_chance(&mut self, percent : u32) {
+        self.decoration_chance = min(percent, 100);
     }
 
-    pub fn get_texture_region(&self, tile : &Tile) -> Option<&TextureRegion> {
-        if self.tiles.contains_key(tile) {
-            return self.tiles.get(tile);
+    pub fn get_texture_region(&self, tile : &Tile, variant : usize) -> Option<&TextureRegion> {
+        self.tiles.get(tile)
+            .and_then(|variants| variants.get(variant))
+            .map(|v| &v.texture_region)
+    }
+
+    pub fn get_decoration_region(&self, tile : &Tile, decoration : usize) -> Option<&TextureRegion> {
+        self.decorations.get(tile)
+            .and_then(|decorations| decorations.get(decoration))
+            .map(|d| &d.texture_region)
+    }
+
+    // picks variants and decorations for every cell, the same seed always gives the same result
+    pub fn decorate(&self, grid : &mut Grid<Cell>, seed : u32) {
+        for y in 0..grid.height as i32 {
+            for x in 0..grid.width as i32 {
+                match grid.get_mut(x, y) {
+                    Some(cell) => {
+                        let hash = cell_hash(x, y, seed);
+                        cell.variant = self.tiles.get(&cell.tile)
+                            .map_or(0, |variants| pick_weighted(variants, hash));
+
+                        cell.decoration = None;
+                        let decoration_hash = cell_hash(x, y, seed ^ 0x9e3779b9);
+                        if decoration_hash % 100 < self.decoration_chance {
+                            cell.decoration = self.decorations.get(&cell.tile)
+                                .map(|decorations| pick_weighted(decorations, decoration_hash / 100));
+                        }
+                    },
+                    _ => {}
+                }
+            }
         }
-        return None;
     }
 }
 
+fn cell_hash(x : i32, y : i32, seed : u32) -> u32 {
+    let mut h = seed;
+    h = (h ^ x as u32).wrapping_mul(0x85ebca6b);
+    h = (h ^ (h >> 13) ^ y as u32).wrapping_mul(0xc2b2ae35);
+    h ^ (h >> 16)
+}
 
-pub fn render_world(context : &mut MotorContext, world: &World, tile_set : &TileSet, camera : &Camera) {
+fn pick_weighted(variants : &Vec<TileVariant>, hash : u32) -> usize {
+    let total = variants.iter().fold(0, |sum, v| sum + v.weight);
+    if total == 0 {
+        return 0;
+    }
+    let mut value = hash % total;
+    for (index, variant) in variants.iter().enumerate() {
+        if value < variant.weight {
+            return index;
+        }
+        value -= variant.weight;
+    }
+    0
+}
+
+
+const REMEMBERED_BRIGHTNESS : u8 = 96;
+// chunk size in tiles
+pub const CHUNK_SIZE : u32 = 16;
+
+// the tiles of a chunk pre-rendered without lighting, split by whether they are drawn below or above the actors
+struct Chunk {
+    below : Option<Texture>,
+    above : Option<Texture>,
+    dirty : bool
+}
 
-    let grid = world.grid.as_ref().unwrap();
-    let actors = &world.actors;
+// draws the tile map from cached chunk textures, lighting and fog of war are applied afterwards
+// by modulating everything with a shade texture holding one pixel per tile
+pub struct WorldRenderer {
+    tile_size : i32,
+    map_size : (u32, u32),
+    chunks_across : u32,
+    chunks : Vec<Chunk>,
+    shade : Option<Texture>
+}
 
-    let tile_size = 8i32;
+impl WorldRenderer {
+    pub fn new(tile_size : i32) -> WorldRenderer {
+        WorldRenderer {
+            tile_size : tile_size,
+            map_size : (0, 0),
+            chunks_across : 0,
+            chunks : Vec::new(),
+            shade : None
+        }
+    }
 
-    let num_tiles_across = camera.size.0 / tile_size as u32;
-    let num_tiles_down = camera.size.1 / tile_size as u32;
+    // call once per frame, before rendering any camera
+    pub fn update(&mut self, context : &mut MotorContext, world : &World, tile_set : &TileSet, changes : &TileChanges) -> MotorResult<()> {
+        let tile_map = match world.tile_map.as_ref() {
+            Some(tile_map) => tile_map,
+            None => return Ok(())
+        };
 
-    let offset_x = camera.position.0 - (camera.size.0 as f64 / 2f64);
-    let offset_y = camera.position.1 - (camera.size.1 as f64 / 2f64);
+        if changes.all || self.map_size != (tile_map.width, tile_map.height) {
+            try!(self.reset(context, tile_map));
+        } else {
+            for &(x, y) in changes.cells.iter() {
+                if x >= 0 && y >= 0 && (x as u32) < self.map_size.0 && (y as u32) < self.map_size.1 {
+                    let index = (y as u32 / CHUNK_SIZE * self.chunks_across + x as u32 / CHUNK_SIZE) as usize;
+                    self.chunks[index].dirty = true;
+                }
+            }
+        }
 
-    let start_x = max(offset_x as i32 / tile_size, 0i32) as u32;
-    let start_y = max(offset_y as i32 / tile_size, 0i32) as u32;
-    let end_x = min(start_x + num_tiles_across + 1, grid.width);
-    let end_y = min(start_y + num_tiles_down + 2, grid.height);
+        for index in 0..self.chunks.len() {
+            if self.chunks[index].dirty {
+                let chunk_x = index as u32 % self.chunks_across;
+                let chunk_y = index as u32 / self.chunks_across;
+                try!(self.render_chunk(context, tile_map, tile_set, index, chunk_x, chunk_y));
+            }
+        }
 
-    for y in start_y..end_y {
-        for x in start_x..end_x {
-            match grid.get(x, y) {
-                Some(cell) => {
-                    let t = &cell.tile;
-                    let texture_region = tile_set.get_texture_region(&t).expect("No texture region for tile");
+        // lights move every frame so the shade is always rewritten
+        try!(self.update_shade(world.fov.as_ref(), world.light_map.as_ref()));
+        Ok(())
+    }
 
-                    let x_pos = x as i32 * tile_size - offset_x as i32;
-                    let y_pos = y as i32 * tile_size - offset_y as i32;
-                    context.render(&tile_set.texture, texture_region, (x_pos, y_pos));
-                },
-                _ => {}
+    // renders every chunk again on the next update, for when the tile set texture changed
+    pub fn invalidate(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.dirty = true;
+        }
+    }
+
+    fn reset(&mut self, context : &mut MotorContext, tile_map : &TileMap) -> MotorResult<()> {
+        self.map_size = (tile_map.width, tile_map.height);
+        self.chunks_across = (tile_map.width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        let chunks_down = (tile_map.height + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        self.chunks = (0..self.chunks_across * chunks_down).map(|_| Chunk { below : None, above : None, dirty : true }).collect();
+
+        let mut shade = try!(context.create_streaming_texture(tile_map.width, tile_map.height));
+        shade.set_blend_mode(BlendMode::Mod);
+        self.shade = Some(shade);
+        Ok(())
+    }
+
+    fn render_chunk(&mut self, context : &mut MotorContext, tile_map : &TileMap, tile_set : &TileSet, index : usize, chunk_x : u32, chunk_y : u32) -> MotorResult<()> {
+        let start = (chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE);
+        let end = (min(start.0 + CHUNK_SIZE, tile_map.width), min(start.1 + CHUNK_SIZE, tile_map.height));
+        let tile_size = self.tile_size;
+        let pixel_size = CHUNK_SIZE * tile_size as u32;
+
+        let chunk = &mut self.chunks[index];
+        chunk.dirty = false;
+        for &above_actors in [false, true].iter() {
+            let layers : Vec<&Layer> = tile_map.layers().iter().filter(|l| l.above_actors == above_actors).collect();
+            let empty = layers.iter().all(|l| l.grid.region(start.0 as i32, start.1 as i32, end.0 - start.0, end.1 - start.1).next().is_none());
+
+            let slot = if above_actors { &mut chunk.above } else { &mut chunk.below };
+            if empty {
+                *slot = None;
+                continue;
+            }
+            let texture = match slot.take() {
+                Some(texture) => texture,
+                None => try!(context.create_target_texture(pixel_size, pixel_size))
             };
+            *slot = Some(try!(context.render_to_texture(texture, |context| {
+                for layer in layers.iter() {
+                    render_layer(context, layer, tile_set, start, end, tile_size);
+                }
+            })));
         }
+        Ok(())
     }
 
-    for actor in actors {
-        let position = actor.get_entity().position;
-        let x = position.x - offset_x;
-        let y = position.y - offset_y;
-        context.render_sprite_at(actor.get_sprite(), x, y);
+    fn update_shade(&mut self, fov : Option<&FieldOfView>, light_map : Option<&LightMap>) -> MotorResult<()> {
+        let (w, h) = self.map_size;
+        let shade = match self.shade.as_mut() {
+            Some(shade) => shade,
+            None => return Ok(())
+        };
+        try!(shade.with_lock(None, |buffer : &mut [u8], pitch : usize| {
+            for y in 0..h {
+                for x in 0..w {
+                    // unexplored tiles stay black, remembered ones are dimmed
+                    let brightness = match fov.map_or(Visibility::Visible, |f| f.get_visibility(x as i32, y as i32)) {
+                        Visibility::Unexplored => 0,
+                        Visibility::Remembered => REMEMBERED_BRIGHTNESS,
+                        Visibility::Visible => 255
+                    };
+                    let light = light_map.map_or((255, 255, 255), |l| l.get_tint(x as i32, y as i32));
+                    let tint = multiply_color(light, (brightness, brightness, brightness));
+                    let offset = y as usize * pitch + x as usize * 3;
+                    buffer[offset] = tint.0;
+                    buffer[offset + 1] = tint.1;
+                    buffer[offset + 2] = tint.2;
+                }
+            }
+        }).map_err(|e| MotorError::TextureLock(format!("{}", e))));
+        Ok(())
+    }
+
+    // renders what one camera sees into its viewport, call it once per camera for split-screen
+    pub fn render(&self, context : &mut MotorContext, world : &World, camera : &Camera) {
+        let tile_map = match world.tile_map.as_ref() {
+            Some(tile_map) => tile_map,
+            None => return
+        };
+        let tile_size = self.tile_size;
+        let zoom = camera.zoom;
+        let fov = world.fov.as_ref();
+
+        let (start_x, start_y, end_x, end_y) = camera.visible_tile_range(tile_size as f64);
+        let (end_x, end_y) = (min(end_x, tile_map.width), min(end_y, tile_map.height));
+        if start_x >= end_x || start_y >= end_y {
+            return;
+        }
+        let chunk_range = (start_x / CHUNK_SIZE, start_y / CHUNK_SIZE,
+                           (end_x + CHUNK_SIZE - 1) / CHUNK_SIZE, (end_y + CHUNK_SIZE - 1) / CHUNK_SIZE);
+
+        context.set_clip_rect(Some(camera.viewport));
+
+        self.render_chunks(context, camera, chunk_range, false);
+
+        let mut queue = RenderQueue::new(true);
+        for actor in world.actors.iter() {
+            let position = actor.get_entity().position;
+            let visible = camera.is_visible(&actor.get_entity().get_rect()) && fov.map_or(true, |f| {
+                f.is_visible((position.x / tile_size as f64).floor() as i32, (position.y / tile_size as f64).floor() as i32)
+            });
+            if !visible {
+                continue;
+            }
+            let (x, y) = camera.world_to_screen(position.x, position.y);
+            queue.push(actor.get_sprite(), actor.get_render_layer(), x.floor(), y.floor(), zoom, (255, 255, 255));
+        }
+        context.render_queue(&mut queue);
+
+        self.render_chunks(context, camera, chunk_range, true);
+
+        // light and fog for everything drawn so far, actors included
+        match self.shade {
+            Some(ref shade) => {
+                let (x, y) = tile_to_screen(camera, start_x, start_y, tile_size);
+                let (next_x, next_y) = tile_to_screen(camera, end_x, end_y, tile_size);
+                let region = TextureRegion::new(start_x, start_y, end_x - start_x, end_y - start_y);
+                context.render_scaled(shade, &region, (x, y), ((next_x - x) as u32, (next_y - y) as u32));
+            },
+            None => {}
+        }
 
         if context.draw_debug_boxes {
-            context.draw_rect(x, y, actor.get_entity().width, actor.get_entity().height);
+            for actor in world.actors.iter() {
+                let entity = actor.get_entity();
+                let (x, y) = camera.world_to_screen(entity.position.x, entity.position.y);
+                context.draw_rect(x.floor(), y.floor(), entity.width * zoom, entity.height * zoom);
+            }
+        }
+
+        context.set_clip_rect(None);
+    }
+
+    fn render_chunks(&self, context : &mut MotorContext, camera : &Camera, (start_x, start_y, end_x, end_y) : (u32, u32, u32, u32), above_actors : bool) {
+        let chunk_pixels = CHUNK_SIZE * self.tile_size as u32;
+        for chunk_y in start_y..end_y {
+            for chunk_x in start_x..end_x {
+                let chunk = &self.chunks[(chunk_y * self.chunks_across + chunk_x) as usize];
+                let texture = if above_actors { &chunk.above } else { &chunk.below };
+                match *texture {
+                    Some(ref texture) => {
+                        let (x, y) = tile_to_screen(camera, chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE, self.tile_size);
+                        let (next_x, next_y) = tile_to_screen(camera, (chunk_x + 1) * CHUNK_SIZE, (chunk_y + 1) * CHUNK_SIZE, self.tile_size);
+                        let region = TextureRegion::new(0, 0, chunk_pixels, chunk_pixels);
+                        context.render_scaled(texture, &region, (x, y), ((next_x - x) as u32, (next_y - y) as u32));
+                    },
+                    None => {}
+                }
+            }
         }
     }
+}
+
+// screen position of a tile corner, consecutive corners are used for the size so zoomed tiles don't leave seams
+fn tile_to_screen(camera : &Camera, x : u32, y : u32, tile_size : i32) -> (i32, i32) {
+    let (screen_x, screen_y) = camera.world_to_screen((x as i32 * tile_size) as f64, (y as i32 * tile_size) as f64);
+    (screen_x.floor() as i32, screen_y.floor() as i32)
+}
 
+// draws the tiles from start to end, relative to start
+fn render_layer(context : &mut MotorContext, layer : &Layer, tile_set : &TileSet, start : (u32, u32), end : (u32, u32), tile_size : i32) {
+    let texture = tile_set.texture.borrow();
+    for (x, y, cell) in layer.grid.region(start.0 as i32, start.1 as i32, end.0 - start.0, end.1 - start.1) {
+        let t = &cell.tile;
+        // tiles the tile set has no region for aren't drawn
+        let texture_region = match tile_set.get_texture_region(&t, cell.variant) {
+            Some(texture_region) => texture_region,
+            None => continue
+        };
+        let position = ((x - start.0 as i32) * tile_size, (y - start.1 as i32) * tile_size);
+        context.render(&texture, texture_region, position);
+
+        match cell.decoration.and_then(|d| tile_set.get_decoration_region(&t, d)) {
+            Some(decoration_region) => {
+                context.render(&texture, decoration_region, position);
+            },
+            _ => {}
+        }
+    }
 }
@@ -0,0 +1,146 @@
+use world::grid::DenseGrid;
+
+// recursive shadowcasting
+// http://www.roguebasin.com/index.php?title=FOV_using_recursive_shadowcasting
+
+const MULTIPLIERS : [[i32; 8]; 4] = [
+    [1, 0, 0, -1, -1, 0, 0, 1],
+    [0, 1, -1, 0, 0, -1, 1, 0],
+    [0, 1, 1, 0, 0, -1, -1, 0],
+    [1, 0, 0, 1, -1, 0, 0, -1]
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Visibility {
+    Unexplored,
+    Remembered,
+    Visible
+}
+
+// calls visit for every tile within radius that can be seen from the origin, including the origin
+pub fn compute_fov<F, V>(origin_x : i32, origin_y : i32, radius : i32, opaque : &F, visit : &mut V)
+    where F : Fn(i32, i32) -> bool, V : FnMut(i32, i32) {
+    visit(origin_x, origin_y);
+    for octant in 0..8 {
+        cast_light(origin_x, origin_y, 1, 1f64, 0f64, radius,
+                   (MULTIPLIERS[0][octant], MULTIPLIERS[1][octant], MULTIPLIERS[2][octant], MULTIPLIERS[3][octant]),
+                   opaque, visit);
+    }
+}
+
+fn cast_light<F, V>(cx : i32, cy : i32, row : i32, start : f64, end : f64, radius : i32,
+                    (xx, xy, yx, yy) : (i32, i32, i32, i32), opaque : &F, visit : &mut V)
+    where F : Fn(i32, i32) -> bool, V : FnMut(i32, i32) {
+    if start < end {
+        return;
+    }
+    let radius_squared = radius * radius;
+    let mut start = start;
+    let mut new_start = 0f64;
+
+    for j in row..(radius + 1) {
+        let mut dx = -j - 1;
+        let dy = -j;
+        let mut blocked = false;
+        while dx <= 0 {
+            dx += 1;
+            let x = cx + dx * xx + dy * xy;
+            let y = cy + dx * yx + dy * yy;
+            let left_slope = (dx as f64 - 0.5f64) / (dy as f64 + 0.5f64);
+            let right_slope = (dx as f64 + 0.5f64) / (dy as f64 - 0.5f64);
+            if start < right_slope {
+                continue;
+            } else if end > left_slope {
+                break;
+            }
+
+            if dx * dx + dy * dy <= radius_squared {
+                visit(x, y);
+            }
+
+            if blocked {
+                if opaque(x, y) {
+                    new_start = right_slope;
+                    continue;
+                } else {
+                    blocked = false;
+                    start = new_start;
+                }
+            } else if opaque(x, y) && j < radius {
+                blocked = true;
+                cast_light(cx, cy, j + 1, start, left_slope, radius, (xx, xy, yx, yy), opaque, visit);
+                new_start = right_slope;
+            }
+        }
+        if blocked {
+            break;
+        }
+    }
+}
+
+pub struct FieldOfView {
+    pub radius : i32,
+    visible : DenseGrid<bool>,
+    explored : DenseGrid<bool>,
+    origin : Option<(i32, i32)>
+}
+
+impl FieldOfView {
+    pub fn new(width : u32, height : u32, radius : i32) -> FieldOfView {
+        FieldOfView {
+            radius : radius,
+            visible : DenseGrid::new(width, height, false),
+            explored : DenseGrid::new(width, height, false),
+            origin : None
+        }
+    }
+
+    // recomputes the visible tiles when the origin has moved, explored tiles are remembered
+    pub fn update<F>(&mut self, origin_x : i32, origin_y : i32, opaque : F)
+        where F : Fn(i32, i32) -> bool {
+        if self.origin == Some((origin_x, origin_y)) {
+            return;
+        }
+        self.origin = Some((origin_x, origin_y));
+        self.visible.fill(false);
+
+        let visible = &mut self.visible;
+        let explored = &mut self.explored;
+        compute_fov(origin_x, origin_y, self.radius, &opaque, &mut |x, y| {
+            visible.set(x, y, true);
+            explored.set(x, y, true);
+        });
+    }
+
+    // forces the next update to recompute, call after tiles changed
+    pub fn invalidate(&mut self) {
+        self.origin = None;
+    }
+
+    // the tile the field of view was last computed from
+    pub fn get_origin(&self) -> Option<(i32, i32)> {
+        self.origin
+    }
+
+    pub fn reveal_all(&mut self) {
+        self.explored.fill(true);
+    }
+
+    pub fn is_visible(&self, x : i32, y : i32) -> bool {
+        self.visible.get(x, y).map_or(false, |v| *v)
+    }
+
+    pub fn is_explored(&self, x : i32, y : i32) -> bool {
+        self.explored.get(x, y).map_or(false, |e| *e)
+    }
+
+    pub fn get_visibility(&self, x : i32, y : i32) -> Visibility {
+        if self.is_visible(x, y) {
+            Visibility::Visible
+        } else if self.is_explored(x, y) {
+            Visibility::Remembered
+        } else {
+            Visibility::Unexplored
+        }
+    }
+}
@@ -1,3 +1,16 @@
+use std::io;
+use std::cmp::{max, min};
+use std::slice::Chunks;
+use std::io::{Read, Write};
+
+// cells without an element are written as this symbol
+pub const EMPTY_SYMBOL : char = ' ';
+
+const BINARY_MAGIC : &'static [u8; 4] = b"GRD1";
+const MAX_RUN_LENGTH : usize = 255;
+// larger headers are taken as corrupt instead of allocating whatever they claim
+const MAX_BINARY_CELLS : u64 = 1 << 24;
+
 pub struct Grid<T> {
     pub width : u32,
     pub height : u32,
@@ -18,39 +31,57 @@ impl <T> Grid <T> {
         }
     }
 
-    pub fn get(&self, x : u32, y : u32) -> Option<&T> {
-        let index = self.width * y + x;
-        if (index as usize) < self.cells.len() {
-            return self.cells[index as usize].as_ref();
+    pub fn in_bounds(&self, x : i32, y : i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn index(&self, x : i32, y : i32) -> Option<usize> {
+        if self.in_bounds(x, y) {
+            return Some((self.width * y as u32 + x as u32) as usize);
         }
         None
     }
 
-    pub fn get_mut(&mut self, x : u32, y : u32) -> Option<&mut T> {
-        let index = self.width * y + x;
-        if (index as usize) < self.cells.len() {
-            return self.cells[index as usize].as_mut();
+    pub fn get(&self, x : i32, y : i32) -> Option<&T> {
+        match self.index(x, y) {
+            Some(index) => self.cells[index].as_ref(),
+            None => None
+        }
+    }
+
+    pub fn get_mut(&mut self, x : i32, y : i32) -> Option<&mut T> {
+        match self.index(x, y) {
+            Some(index) => self.cells[index].as_mut(),
+            None => None
+        }
+    }
+
+    pub fn set(&mut self, x : i32, y: i32, element : T) {
+        match self.index(x, y) {
+            Some(index) => self.cells[index] = Some(element),
+            None => {}
         }
-        None
     }
 
-    pub fn set(&mut self, x : u32, y: u32, element : T) {
-        let index = self.width * y + x;
-        if (index as usize) < self.cells.len() {
-            self.cells[index as usize] = Some(element);
+    pub fn remove(&mut self, x : i32, y: i32) -> Option<T> {
+        match self.index(x, y) {
+            Some(index) => self.cells[index].take(),
+            None => None
         }
     }
 
-    pub fn fill<F>(&mut self, x : u32, y: u32, w: u32, h : u32, f : F)
+    // the part of the rectangle outside the grid is ignored, f is only called for cells inside it
+    pub fn fill_rect<F>(&mut self, x : i32, y: i32, w: u32, h : u32, f : F)
         where F : Fn() -> T {
-        for yy in y..(y + h) {
-            for xx in x..(x + w) {
+        let (x0, y0, x1, y1) = clip(self.width, self.height, x, y, w, h);
+        for yy in y0..y1 {
+            for xx in x0..x1 {
                 self.set(xx, yy, f());
             }
         }
     }
 
-    pub fn get_if<F>(&self, x : u32, y: u32, f : F) -> Option<&T>
+    pub fn get_if<F>(&self, x : i32, y: i32, f : F) -> Option<&T>
         where F : Fn(&T) -> bool {
 
         let element = self.get(x, y);
@@ -61,4 +92,520 @@ impl <T> Grid <T> {
         None
     }
 
+    pub fn iter(&self) -> Cells<T> {
+        self.region(0, 0, self.width, self.height)
+    }
+
+    pub fn row(&self, y : i32) -> Cells<T> {
+        self.region(0, y, self.width, 1)
+    }
+
+    pub fn column(&self, x : i32) -> Cells<T> {
+        self.region(x, 0, 1, self.height)
+    }
+
+    // iterates the occupied cells of a rectangle as (x, y, element), clipped to the grid
+    pub fn region(&self, x : i32, y : i32, w : u32, h : u32) -> Cells<T> {
+        let (x0, y0, x1, y1) = clip(self.width, self.height, x, y, w, h);
+        Cells {
+            grid : self,
+            x0 : x0,
+            x1 : x1,
+            y1 : y1,
+            x : x0,
+            y : y0
+        }
+    }
+
+    pub fn neighbors_4(&self, x : i32, y : i32) -> Neighbors<T> {
+        Neighbors {
+            grid : self,
+            x : x,
+            y : y,
+            offsets : &OFFSETS_4,
+            index : 0
+        }
+    }
+
+    pub fn neighbors_8(&self, x : i32, y : i32) -> Neighbors<T> {
+        Neighbors {
+            grid : self,
+            x : x,
+            y : y,
+            offsets : &OFFSETS_8,
+            index : 0
+        }
+    }
+
+    pub fn map<U, F>(&self, f : F) -> Grid<U>
+        where F : Fn(&T) -> U {
+        Grid {
+            width : self.width,
+            height : self.height,
+            cells : self.cells.iter().map(|cell| cell.as_ref().map(|element| f(element))).collect()
+        }
+    }
+
+    // copies the occupied cells of source with its top left corner at x, y
+    pub fn blit(&mut self, source : &Grid<T>, x : i32, y : i32)
+        where T : Clone {
+        for (sx, sy, element) in source.iter() {
+            self.set(x + sx, y + sy, element.clone());
+        }
+    }
+
+    pub fn to_dense(&self, empty : T) -> DenseGrid<T>
+        where T : Clone {
+        DenseGrid {
+            width : self.width,
+            height : self.height,
+            cells : self.cells.iter().map(|cell| cell.as_ref().unwrap_or(&empty).clone()).collect()
+        }
+    }
+
+    // fails on elements the codec has no symbol for, they couldn't be read back
+    pub fn to_ascii<C>(&self, codec : &C) -> Result<String, String>
+        where C : GridCodec<T> {
+        let mut output = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let symbol = match self.get(x as i32, y as i32) {
+                    Some(element) => try!(codec.encode(element).ok_or_else(|| format!("no symbol for the element at {}, {}", x, y))),
+                    None => EMPTY_SYMBOL
+                };
+                output.push(symbol);
+            }
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    // lines shorter than the widest line are padded with empty cells
+    pub fn from_ascii<C>(text : &str, codec : &C) -> Result<Grid<T>, String>
+        where C : GridCodec<T> {
+        let lines = text.lines().collect::<Vec<&str>>();
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let mut grid = Grid::new(width as u32, lines.len() as u32);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, symbol) in line.chars().enumerate() {
+                if symbol == EMPTY_SYMBOL {
+                    continue;
+                }
+                match codec.decode(symbol) {
+                    Some(element) => grid.set(x as i32, y as i32, element),
+                    None => return Err(format!("unknown symbol '{}' at line {} column {}", symbol, y + 1, x + 1))
+                }
+            }
+        }
+        Ok(grid)
+    }
+
+    // run length encoded, the header is followed by (count, symbol) byte pairs
+    pub fn write_binary<W, C>(&self, writer : &mut W, codec : &C) -> io::Result<()>
+        where W : Write, C : GridCodec<T> {
+        try!(writer.write_all(BINARY_MAGIC));
+        try!(writer.write_all(&u32_to_bytes(self.width)));
+        try!(writer.write_all(&u32_to_bytes(self.height)));
+
+        let mut runs = Vec::new();
+        let mut current : Option<(u8, usize)> = None;
+        for cell in self.cells.iter() {
+            let symbol = match cell.as_ref() {
+                Some(element) => try!(codec.encode(element)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no symbol for an element of the grid"))),
+                None => EMPTY_SYMBOL
+            };
+            if symbol as u32 > 127 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("symbol '{}' is not ascii", symbol)));
+            }
+            let byte = symbol as u8;
+            current = match current {
+                Some((b, count)) if b == byte && count < MAX_RUN_LENGTH => Some((b, count + 1)),
+                Some((b, count)) => {
+                    runs.push(count as u8);
+                    runs.push(b);
+                    Some((byte, 1))
+                },
+                None => Some((byte, 1))
+            };
+        }
+        match current {
+            Some((b, count)) => {
+                runs.push(count as u8);
+                runs.push(b);
+            },
+            None => {}
+        }
+        writer.write_all(&runs)
+    }
+
+    pub fn read_binary<R, C>(reader : &mut R, codec : &C) -> io::Result<Grid<T>>
+        where R : Read, C : GridCodec<T> {
+        let mut header = [0u8; 12];
+        try!(read_exact(reader, &mut header));
+        if &header[0..4] != &BINARY_MAGIC[..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a grid file"));
+        }
+        let width = bytes_to_u32(&header[4..8]);
+        let height = bytes_to_u32(&header[8..12]);
+        if width as u64 * height as u64 > MAX_BINARY_CELLS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("grid of {}x{} is too large", width, height)));
+        }
+
+        let mut grid = Grid::new(width, height);
+        let size = grid.cells.len();
+        let mut index = 0;
+        let mut run = [0u8; 2];
+        while index < size {
+            try!(read_exact(reader, &mut run));
+            let count = run[0] as usize;
+            let symbol = run[1] as char;
+            if count == 0 || index + count > size {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid run length"));
+            }
+            if symbol != EMPTY_SYMBOL {
+                for i in index..(index + count) {
+                    match codec.decode(symbol) {
+                        Some(element) => grid.cells[i] = Some(element),
+                        None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown symbol '{}'", symbol)))
+                    }
+                }
+            }
+            index += count;
+        }
+        Ok(grid)
+    }
+}
+
+const OFFSETS_4 : [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+const OFFSETS_8 : [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+fn clip(width : u32, height : u32, x : i32, y : i32, w : u32, h : u32) -> (i32, i32, i32, i32) {
+    let x0 = max(x, 0);
+    let y0 = max(y, 0);
+    let x1 = min(x + w as i32, width as i32);
+    let y1 = min(y + h as i32, height as i32);
+    (x0, y0, max(x0, x1), max(y0, y1))
+}
+
+pub struct Cells<'a, T : 'a> {
+    grid : &'a Grid<T>,
+    x0 : i32,
+    x1 : i32,
+    y1 : i32,
+    x : i32,
+    y : i32
+}
+
+impl<'a, T> Iterator for Cells<'a, T> {
+    type Item = (i32, i32, &'a T);
+
+    fn next(&mut self) -> Option<(i32, i32, &'a T)> {
+        while self.y < self.y1 {
+            let (x, y) = (self.x, self.y);
+            if x >= self.x1 {
+                self.x = self.x0;
+                self.y += 1;
+                continue;
+            }
+            self.x += 1;
+            match self.grid.get(x, y) {
+                Some(element) => return Some((x, y, element)),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+pub struct Neighbors<'a, T : 'a> {
+    grid : &'a Grid<T>,
+    x : i32,
+    y : i32,
+    offsets : &'static [(i32, i32)],
+    index : usize
+}
+
+impl<'a, T> Iterator for Neighbors<'a, T> {
+    type Item = (i32, i32, &'a T);
+
+    fn next(&mut self) -> Option<(i32, i32, &'a T)> {
+        while self.index < self.offsets.len() {
+            let (dx, dy) = self.offsets[self.index];
+            self.index += 1;
+            let (x, y) = (self.x + dx, self.y + dy);
+            match self.grid.get(x, y) {
+                Some(element) => return Some((x, y, element)),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+// a grid where every cell holds a value, for fully populated maps
+pub struct DenseGrid<T> {
+    pub width : u32,
+    pub height : u32,
+    cells : Vec<T>
+}
+
+impl<T : Clone> DenseGrid<T> {
+    pub fn new(width : u32, height : u32, value : T) -> DenseGrid<T> {
+        DenseGrid {
+            width : width,
+            height : height,
+            cells : vec![value; (width * height) as usize]
+        }
+    }
+
+    pub fn in_bounds(&self, x : i32, y : i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn index(&self, x : i32, y : i32) -> Option<usize> {
+        if self.in_bounds(x, y) {
+            return Some((self.width * y as u32 + x as u32) as usize);
+        }
+        None
+    }
+
+    pub fn get(&self, x : i32, y : i32) -> Option<&T> {
+        self.index(x, y).map(|index| &self.cells[index])
+    }
+
+    pub fn get_mut(&mut self, x : i32, y : i32) -> Option<&mut T> {
+        match self.index(x, y) {
+            Some(index) => Some(&mut self.cells[index]),
+            None => None
+        }
+    }
+
+    pub fn set(&mut self, x : i32, y : i32, value : T) {
+        match self.index(x, y) {
+            Some(index) => self.cells[index] = value,
+            None => {}
+        }
+    }
+
+    pub fn fill(&mut self, value : T) {
+        for cell in self.cells.iter_mut() {
+            *cell = value.clone();
+        }
+    }
+
+    pub fn fill_rect(&mut self, x : i32, y : i32, w : u32, h : u32, value : T) {
+        let (x0, y0, x1, y1) = clip(self.width, self.height, x, y, w, h);
+        for yy in y0..y1 {
+            for xx in x0..x1 {
+                self.set(xx, yy, value.clone());
+            }
+        }
+    }
+
+    pub fn neighbors_4(&self, x : i32, y : i32) -> DenseNeighbors<T> {
+        DenseNeighbors {
+            grid : self,
+            x : x,
+            y : y,
+            offsets : &OFFSETS_4,
+            index : 0
+        }
+    }
+
+    pub fn neighbors_8(&self, x : i32, y : i32) -> DenseNeighbors<T> {
+        DenseNeighbors {
+            grid : self,
+            x : x,
+            y : y,
+            offsets : &OFFSETS_8,
+            index : 0
+        }
+    }
+
+    pub fn map<U, F>(&self, f : F) -> DenseGrid<U>
+        where F : Fn(&T) -> U {
+        DenseGrid {
+            width : self.width,
+            height : self.height,
+            cells : self.cells.iter().map(|value| f(value)).collect()
+        }
+    }
+
+    pub fn blit(&mut self, source : &DenseGrid<T>, x : i32, y : i32) {
+        for sy in 0..source.height as i32 {
+            for sx in 0..source.width as i32 {
+                self.set(x + sx, y + sy, source.get(sx, sy).unwrap().clone());
+            }
+        }
+    }
+
+    // a grid without columns has no cells, so it has no rows either
+    pub fn rows(&self) -> Chunks<T> {
+        self.cells.chunks(max(self.width, 1) as usize)
+    }
+}
+
+pub struct DenseNeighbors<'a, T : 'a> {
+    grid : &'a DenseGrid<T>,
+    x : i32,
+    y : i32,
+    offsets : &'static [(i32, i32)],
+    index : usize
+}
+
+impl<'a, T : Clone> Iterator for DenseNeighbors<'a, T> {
+    type Item = (i32, i32, &'a T);
+
+    fn next(&mut self) -> Option<(i32, i32, &'a T)> {
+        while self.index < self.offsets.len() {
+            let (dx, dy) = self.offsets[self.index];
+            self.index += 1;
+            let (x, y) = (self.x + dx, self.y + dy);
+            match self.grid.get(x, y) {
+                Some(value) => return Some((x, y, value)),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+// maps grid elements to the symbols used in the ascii and binary formats
+pub trait GridCodec<T> {
+    // None for elements without a symbol
+    fn encode(&self, element : &T) -> Option<char>;
+    fn decode(&self, symbol : char) -> Option<T>;
+}
+
+pub struct Legend<T> {
+    entries : Vec<(char, T)>
+}
+
+impl<T : PartialEq + Clone> Legend<T> {
+    pub fn new() -> Legend<T> {
+        Legend {
+            entries : Vec::new()
+        }
+    }
+
+    pub fn add(mut self, symbol : char, element : T) -> Legend<T> {
+        if symbol == EMPTY_SYMBOL {
+            panic!("'{}' is reserved for empty cells", EMPTY_SYMBOL);
+        }
+        self.entries.push((symbol, element));
+        self
+    }
+
+    pub fn get_symbol(&self, element : &T) -> Option<char> {
+        self.entries.iter().find(|e| e.1 == *element).map(|e| e.0)
+    }
+
+    pub fn get_element(&self, symbol : char) -> Option<T> {
+        self.entries.iter().find(|e| e.0 == symbol).map(|e| e.1.clone())
+    }
+}
+
+impl<T : PartialEq + Clone> GridCodec<T> for Legend<T> {
+    fn encode(&self, element : &T) -> Option<char> {
+        self.get_symbol(element)
+    }
+
+    fn decode(&self, symbol : char) -> Option<T> {
+        self.get_element(symbol)
+    }
+}
+
+pub fn u32_to_bytes(value : u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+pub fn bytes_to_u32(bytes : &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+pub fn read_exact<R : Read>(reader : &mut R, buffer : &mut [u8]) -> io::Result<()> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match try!(reader.read(&mut buffer[read..])) {
+            0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of grid data")),
+            n => read += n
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(PartialEq, Clone, Debug)]
+    enum Tile {
+        Wall,
+        Floor,
+        Door
+    }
+
+    fn legend() -> Legend<Tile> {
+        Legend::new()
+            .add('#', Tile::Wall)
+            .add('.', Tile::Floor)
+    }
+
+    #[test]
+    fn elements_without_a_symbol_are_not_written() {
+        let mut grid = Grid::new(2, 1);
+        grid.set(0, 0, Tile::Wall);
+        grid.set(1, 0, Tile::Door);
+        assert!(grid.to_ascii(&legend()).is_err());
+        assert!(grid.write_binary(&mut Vec::new(), &legend()).is_err());
+    }
+
+    #[test]
+    fn empty_cells_survive_a_round_trip() {
+        let grid = Grid::from_ascii("#.#\n# \n", &legend()).unwrap();
+        assert_eq!(grid.get(1, 1), None);
+        let mut bytes = Vec::new();
+        grid.write_binary(&mut bytes, &legend()).unwrap();
+        let read = Grid::read_binary(&mut &bytes[..], &legend()).unwrap();
+        assert_eq!(read.to_ascii(&legend()).unwrap(), "#.#\n#  \n");
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend(u32_to_bytes(100000).iter());
+        bytes.extend(u32_to_bytes(100000).iter());
+        assert!(Grid::<Tile>::read_binary(&mut &bytes[..], &legend()).is_err());
+    }
+
+    #[test]
+    fn fill_rect_clips_to_the_grid() {
+        let mut grid = Grid::new(3, 2);
+        let calls = ::std::cell::Cell::new(0);
+        grid.fill_rect(-1, 1, 3, 5, || { calls.set(calls.get() + 1); Tile::Floor });
+        assert_eq!(calls.get(), 2);
+        assert_eq!(grid.to_ascii(&legend()).unwrap(), "   \n.. \n");
+
+        let mut dense = DenseGrid::new(3, 2, 0);
+        dense.fill_rect(-1, 1, 3, 5, 1);
+        assert_eq!(dense.rows().map(|row| row.to_vec()).collect::<Vec<_>>(), vec![vec![0, 0, 0], vec![1, 1, 0]]);
+    }
+
+    #[test]
+    fn neighbors_skip_cells_outside_the_grid() {
+        let mut grid = Grid::new(2, 2);
+        grid.fill_rect(0, 0, 2, 2, || Tile::Floor);
+        let dense = grid.to_dense(Tile::Wall);
+        assert_eq!(grid.neighbors_4(0, 0).map(|(x, y, _)| (x, y)).collect::<Vec<_>>(), vec![(1, 0), (0, 1)]);
+        assert_eq!(dense.neighbors_4(0, 0).map(|(x, y, _)| (x, y)).collect::<Vec<_>>(), vec![(1, 0), (0, 1)]);
+        assert_eq!(grid.neighbors_8(0, 0).count(), 3);
+        assert_eq!(dense.neighbors_8(0, 0).count(), 3);
+    }
+
+    #[test]
+    fn empty_dense_grids_have_no_rows() {
+        assert_eq!(DenseGrid::new(0, 3, 0).rows().count(), 0);
+        assert_eq!(DenseGrid::new(3, 0, 0).rows().count(), 0);
+    }
 }
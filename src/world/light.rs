@@ -0,0 +1,123 @@
+use world::fov::compute_fov;
+use world::grid::DenseGrid;
+use world::tilemap::TileMap;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub x : f64,
+    pub y : f64,
+    pub color : (u8, u8, u8),
+    // in world units, the light fades to nothing at the radius
+    pub radius : f64,
+    pub intensity : f64
+}
+
+impl Light {
+    pub fn new(x : f64, y : f64, color : (u8, u8, u8), radius : f64) -> Light {
+        Light {
+            x : x,
+            y : y,
+            color : color,
+            radius : radius,
+            intensity : 1f64
+        }
+    }
+
+    // quadratic falloff from the center to the radius
+    fn get_falloff(&self, distance : f64) -> f64 {
+        if distance >= self.radius {
+            return 0f64;
+        }
+        let f = 1f64 - distance / self.radius;
+        f * f * self.intensity
+    }
+}
+
+// the light reaching each tile, ambient light included
+pub struct LightMap {
+    pub ambient : (u8, u8, u8),
+    values : DenseGrid<(f64, f64, f64)>
+}
+
+impl LightMap {
+    pub fn new(width : u32, height : u32, ambient : (u8, u8, u8)) -> LightMap {
+        LightMap {
+            ambient : ambient,
+            values : DenseGrid::new(width, height, (0f64, 0f64, 0f64))
+        }
+    }
+
+    // the lights emitted by the tiles themselves, these don't move so collect them once per level
+    pub fn collect_tile_lights(tile_map : &TileMap, tile_size : f64) -> Vec<Light> {
+        let mut lights = Vec::new();
+        for layer in tile_map.layers() {
+            for (x, y, cell) in layer.grid.iter() {
+                match tile_map.tile_defs.get(&cell.tile).light {
+                    Some((color, radius)) => {
+                        let center_x = (x as f64 + 0.5f64) * tile_size;
+                        let center_y = (y as f64 + 0.5f64) * tile_size;
+                        lights.push(Light::new(center_x, center_y, color, radius));
+                    },
+                    None => {}
+                }
+            }
+        }
+        lights
+    }
+
+    pub fn compute(&mut self, tile_map : &TileMap, lights : &[Light], tile_size : f64) {
+        let ambient = (self.ambient.0 as f64 / 255f64, self.ambient.1 as f64 / 255f64, self.ambient.2 as f64 / 255f64);
+        self.values.fill(ambient);
+
+        let values = &mut self.values;
+        let opaque = |x, y| tile_map.blocks_sight(x, y);
+        for light in lights.iter() {
+            let origin_x = (light.x / tile_size).floor() as i32;
+            let origin_y = (light.y / tile_size).floor() as i32;
+            let radius = (light.radius / tile_size).ceil() as i32;
+            compute_fov(origin_x, origin_y, radius, &opaque, &mut |x, y| {
+                let dx = (x as f64 + 0.5f64) * tile_size - light.x;
+                let dy = (y as f64 + 0.5f64) * tile_size - light.y;
+                let falloff = light.get_falloff((dx * dx + dy * dy).sqrt());
+                if falloff > 0f64 {
+                    match values.get_mut(x, y) {
+                        Some(value) => {
+                            value.0 += light.color.0 as f64 / 255f64 * falloff;
+                            value.1 += light.color.1 as f64 / 255f64 * falloff;
+                            value.2 += light.color.2 as f64 / 255f64 * falloff;
+                        },
+                        None => {}
+                    }
+                }
+            });
+        }
+    }
+
+    // color to modulate a tile or sprite with
+    pub fn get_tint(&self, x : i32, y : i32) -> (u8, u8, u8) {
+        match self.values.get(x, y) {
+            Some(&(r, g, b)) => (to_channel(r), to_channel(g), to_channel(b)),
+            None => self.ambient
+        }
+    }
+
+    pub fn get_tint_at_position(&self, x : f64, y : f64, tile_size : f64) -> (u8, u8, u8) {
+        self.get_tint((x / tile_size).floor() as i32, (y / tile_size).floor() as i32)
+    }
+}
+
+fn to_channel(value : f64) -> u8 {
+    if value >= 1f64 {
+        255
+    } else if value <= 0f64 {
+        0
+    } else {
+        (value * 255f64) as u8
+    }
+}
+
+pub fn multiply_color(a : (u8, u8, u8), b : (u8, u8, u8)) -> (u8, u8, u8) {
+    ((a.0 as u32 * b.0 as u32 / 255) as u8,
+     (a.1 as u32 * b.1 as u32 / 255) as u8,
+     (a.2 as u32 * b.2 as u32 / 255) as u8)
+}
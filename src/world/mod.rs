@@ -1,6 +1,12 @@
 extern crate nalgebra as na;
 
 pub mod grid;
+pub mod tilemap;
+pub mod tiledef;
+pub mod tiled;
+pub mod raycast;
+pub mod fov;
+pub mod light;
 
 use rand::{Rng, Rand};
 use self::na::*;
@@ -9,14 +15,52 @@ use std::ops::{Add, Mul};
 use motor::MotorContext;
 use motor::gfx::Sprite;
 
-#[derive(PartialEq, Eq, Hash)]
+use self::tilemap::TileMap;
+use self::fov::FieldOfView;
+use self::light::{Light, LightMap};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum Tile {
     Grass,
     Water,
 
     Solid,
     Wall,
-    Floor
+    Floor,
+    Lava,
+    Ice
+}
+
+pub const TILES : [Tile; 7] = [Tile::Grass, Tile::Water, Tile::Solid, Tile::Wall, Tile::Floor, Tile::Lava, Tile::Ice];
+
+impl Tile {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Tile::Grass => "grass",
+            Tile::Water => "water",
+            Tile::Solid => "solid",
+            Tile::Wall => "wall",
+            Tile::Floor => "floor",
+            Tile::Lava => "lava",
+            Tile::Ice => "ice"
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<Tile> {
+        TILES.iter().find(|t| t.name() == name).map(|t| *t)
+    }
+}
+
+// symbols used for ascii and binary snapshots of the tile layers
+pub fn tile_legend() -> grid::Legend<Tile> {
+    grid::Legend::new()
+        .add('"', Tile::Grass)
+        .add('~', Tile::Water)
+        .add('#', Tile::Solid)
+        .add('W', Tile::Wall)
+        .add('.', Tile::Floor)
+        .add('%', Tile::Lava)
+        .add('_', Tile::Ice)
 }
 
 impl Rand for Tile {
@@ -31,17 +75,38 @@ impl Rand for Tile {
 
 pub struct Cell {
     pub tile : Tile,
+    pub variant : usize,
+    pub decoration : Option<usize>,
+    pub hit_points : Option<u32>
 }
 
 impl Cell {
     pub fn new(tile : Tile) -> Cell {
         Cell {
-            tile : tile
+            tile : tile,
+            variant : 0,
+            decoration : None,
+            hit_points : None
         }
     }
 }
 
+impl grid::GridCodec<Cell> for grid::Legend<Tile> {
+    fn encode(&self, cell : &Cell) -> Option<char> {
+        self.get_symbol(&cell.tile)
+    }
+
+    fn decode(&self, symbol : char) -> Option<Cell> {
+        self.get_element(symbol).map(|tile| Cell::new(tile))
+    }
+}
 
+pub struct SpawnPoint {
+    pub name : String,
+    pub kind : String,
+    pub x : f64,
+    pub y : f64
+}
 
 pub struct Entity {
     pub position : Vec2<f64>,
@@ -68,10 +133,18 @@ impl Entity {
         self.position.y = y;
     }
 
+    pub fn get_center(&self) -> (f64, f64) {
+        (self.position.x + self.width / 2f64, self.position.y + self.height / 2f64)
+    }
+
+    pub fn get_rect(&self) -> Rectangle {
+        Rectangle::new(self.position.x, self.position.y, self.width, self.height)
+    }
+
 }
 
 #[derive (Clone, Copy, Debug)]
-struct Rectangle {
+pub struct Rectangle {
     pub x : f64,
     pub y : f64,
     pub w : f64,
@@ -124,7 +197,7 @@ impl CollisionData {
 }
 
 
-pub fn move_entity(entity : &mut Entity, delta_time : f64, grid : &grid::Grid<Cell>) -> bool {
+pub fn move_entity(entity : &mut Entity, delta_time : f64, tile_map : &TileMap) -> bool {
 
     let mut collision = false;
 
@@ -138,15 +211,15 @@ pub fn move_entity(entity : &mut Entity, delta_time : f64, grid : &grid::Grid<Ce
     let mut start_y;
     let mut end_y;
     if entity.velocity.x > 0f64 {
-        start_x = ((entity_rect.x + entity_rect.w + entity.velocity.x)/tile_size) as u32;
+        start_x = tile_index(entity_rect.x + entity_rect.w + entity.velocity.x, tile_size);
         end_x = start_x;
     } else {
-        start_x = ((entity_rect.x + entity.velocity.x)/tile_size) as u32;
+        start_x = tile_index(entity_rect.x + entity.velocity.x, tile_size);
         end_x = start_x;
     }
-    start_y = (entity_rect.y / tile_size) as u32;
-    end_y = ((entity_rect.y + entity_rect.w) / tile_size) as u32;
-    get_collision_tiles(start_x, end_x, start_y, end_y, grid, &mut entity.collision_data);
+    start_y = tile_index(entity_rect.y, tile_size);
+    end_y = tile_index(entity_rect.y + entity_rect.w, tile_size);
+    get_collision_tiles(start_x, end_x, start_y, end_y, tile_map, &mut entity.collision_data);
     entity_rect.x += entity.velocity.x;
     'x_loop: for i in 0..entity.collision_data.count {
         if entity_rect.overlaps(&entity.collision_data.rects[i]) {
@@ -158,14 +231,14 @@ pub fn move_entity(entity : &mut Entity, delta_time : f64, grid : &grid::Grid<Ce
     entity_rect.x = entity.position.x;
 
     if entity.velocity.y > 0f64 {
-        start_y = ((entity_rect.y + entity_rect.h + entity.velocity.y)/tile_size) as u32;
+        start_y = tile_index(entity_rect.y + entity_rect.h + entity.velocity.y, tile_size);
         end_y = start_y;
     } else {
-        start_y = ((entity_rect.y + entity.velocity.y)/tile_size) as u32;
+        start_y = tile_index(entity_rect.y + entity.velocity.y, tile_size);
     }
-    start_x = (entity_rect.x / tile_size) as u32;
-    end_x = ((entity_rect.x + entity_rect.w) / tile_size) as u32;
-    get_collision_tiles(start_x, end_x, start_y, end_y, grid, &mut entity.collision_data);
+    start_x = tile_index(entity_rect.x, tile_size);
+    end_x = tile_index(entity_rect.x + entity_rect.w, tile_size);
+    get_collision_tiles(start_x, end_x, start_y, end_y, tile_map, &mut entity.collision_data);
     entity_rect.y += entity.velocity.y;
     'y_loop: for i in 0..entity.collision_data.count {
         if entity_rect.overlaps(&entity.collision_data.rects[i]) {
@@ -179,21 +252,18 @@ pub fn move_entity(entity : &mut Entity, delta_time : f64, grid : &grid::Grid<Ce
     collision
 }
 
-fn get_collision_tiles(start_x : u32, end_x : u32, start_y : u32, end_y : u32, grid : &grid::Grid<Cell>, collision_data : &mut CollisionData) {
+// floor, so positions left of or above the map give negative indices
+pub fn tile_index(position : f64, tile_size : f64) -> i32 {
+    (position / tile_size).floor() as i32
+}
+
+fn get_collision_tiles(start_x : i32, end_x : i32, start_y : i32, end_y : i32, tile_map : &TileMap, collision_data : &mut CollisionData) {
     let size = 8f64;
     collision_data.reset();
 
     for y in start_y..(end_y + 1) {
         for x in start_x..(end_x + 1) {
-            let t = grid.get_if(x, y, |cell| {
-                match cell.tile {
-                    Tile::Solid | Tile::Wall => {
-                        true
-                    }
-                    _ => { false }
-                }
-            });
-            if t.is_some() {
+            if tile_map.is_solid(x, y) {
                 collision_data.add(x as f64 * size, y as f64 * size, size, size);
             }
         }
@@ -202,38 +272,87 @@ fn get_collision_tiles(start_x : u32, end_x : u32, start_y : u32, end_y : u32, g
 
 pub enum Action {
     None,
-    Fire {x: f64, y : f64, velocity_x : f64, velocity_y : f64}
+    Fire {x: f64, y : f64, velocity_x : f64, velocity_y : f64},
+    DamageTile {x : i32, y : i32, amount : u32},
+    // the player lost all health, it stays in the world and is moved back to the start
+    PlayerDied
 }
 
 pub trait Actor {
-    fn update(&mut self, context : &mut MotorContext, delta_time : f64,  grid : &grid::Grid<Cell>) -> Action;
+    fn update(&mut self, context : &mut MotorContext, delta_time : f64, tile_map : &TileMap) -> Action;
     fn is_alive(&self) -> bool;
     fn get_entity(&self) -> &Entity;
     fn get_entity_mut(&mut self) -> &mut Entity;
     fn get_sprite(&self) -> &Sprite;
+    fn get_light(&self) -> Option<Light> {
+        None
+    }
+    // actors with a higher layer are drawn on top, within a layer lower actors are drawn on top
+    fn get_render_layer(&self) -> i32 {
+        0
+    }
 }
 
+const FOV_RADIUS : i32 = 10;
+
 pub struct World {
-    pub grid : Option<grid::Grid<Cell>>,
+    pub tile_map : Option<TileMap>,
+    pub fov : Option<FieldOfView>,
+    pub light_map : Option<LightMap>,
+    tile_lights : Vec<Light>,
+    // actors are removed once they are no longer alive, the game keeps its player first and alive
     pub actors : Vec<Box<Actor>>,
 }
 
 impl World {
     pub fn new() -> World {
         World {
-            grid : None,
+            tile_map : None,
+            fov : None,
+            light_map : None,
+            tile_lights : Vec::new(),
             actors : Vec::new()
         }
     }
 
-    pub fn init(&mut self, grid : grid::Grid<Cell>) {
-        self.grid = Some(grid);
+    pub fn init(&mut self, tile_map : TileMap, tile_size : f64) {
+        self.fov = Some(FieldOfView::new(tile_map.width, tile_map.height, FOV_RADIUS));
+        self.light_map = Some(LightMap::new(tile_map.width, tile_map.height, tile_map.ambient_light));
+        self.tile_map = Some(tile_map);
+        self.refresh_tile_lights(tile_size);
+    }
+
+    // lights placed by tiles, call after tiles changed
+    pub fn refresh_tile_lights(&mut self, tile_size : f64) {
+        self.tile_lights = match self.tile_map.as_ref() {
+            Some(tile_map) => LightMap::collect_tile_lights(tile_map, tile_size),
+            None => Vec::new()
+        };
+    }
+
+    pub fn update_lights(&mut self, tile_size : f64) {
+        let mut lights = self.tile_lights.clone();
+        lights.extend(self.actors.iter().filter_map(|a| a.get_light()));
+        match (self.tile_map.as_ref(), self.light_map.as_mut()) {
+            (Some(tile_map), Some(light_map)) => light_map.compute(tile_map, &lights, tile_size),
+            _ => {}
+        }
+    }
+
+    // recomputes what can be seen from the given position, call after the viewer moved
+    pub fn update_fov(&mut self, x : f64, y : f64, tile_size : f64) {
+        match (self.tile_map.as_ref(), self.fov.as_mut()) {
+            (Some(tile_map), Some(fov)) => {
+                fov.update(tile_index(x, tile_size), tile_index(y, tile_size), |tx, ty| tile_map.blocks_sight(tx, ty));
+            },
+            _ => {}
+        }
     }
 
     pub fn update(&mut self, context : &mut MotorContext, delta_time : f64, actions : &mut Vec<Action>) {
-        if self.grid.is_some() {
+        if self.tile_map.is_some() {
             for actor in self.actors.iter_mut() {
-                let action = actor.update(context, delta_time, self.grid.as_ref().unwrap());
+                let action = actor.update(context, delta_time, self.tile_map.as_ref().unwrap());
                 match action {
                     Action::None => {},
                     _ => { actions.push(action); }
@@ -0,0 +1,170 @@
+use world::Cell;
+use world::grid::Grid;
+use world::tilemap::TileMap;
+
+// http://www.cse.yorku.ca/~amana/research/grid.pdf
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub tile_x : i32,
+    pub tile_y : i32,
+    pub x : f64,
+    pub y : f64,
+    pub distance : f64
+}
+
+// walks the tiles between two points in world coordinates and returns the first one
+// for which blocks returns true. the tile the ray starts in is never reported, a ray
+// through the corner of four tiles is stopped by either of the two tiles beside the corner.
+pub fn raycast<F>(from : (f64, f64), to : (f64, f64), tile_size : f64, blocks : F) -> Option<RayHit>
+    where F : Fn(i32, i32) -> bool {
+
+    let dx = to.0 - from.0;
+    let dy = to.1 - from.1;
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0f64 {
+        return None;
+    }
+    let direction = (dx / length, dy / length);
+
+    let mut tile_x = (from.0 / tile_size).floor() as i32;
+    let mut tile_y = (from.1 / tile_size).floor() as i32;
+    let end_x = (to.0 / tile_size).floor() as i32;
+    let end_y = (to.1 / tile_size).floor() as i32;
+
+    let step_x = if direction.0 > 0f64 { 1 } else { -1 };
+    let step_y = if direction.1 > 0f64 { 1 } else { -1 };
+
+    // distance along the ray to cross one whole tile on each axis
+    let delta_x = if direction.0 != 0f64 { (tile_size / direction.0).abs() } else { ::std::f64::INFINITY };
+    let delta_y = if direction.1 != 0f64 { (tile_size / direction.1).abs() } else { ::std::f64::INFINITY };
+
+    // distance along the ray to the first tile border on each axis
+    let mut next_x = if direction.0 > 0f64 {
+        ((tile_x + 1) as f64 * tile_size - from.0) / direction.0
+    } else if direction.0 < 0f64 {
+        (tile_x as f64 * tile_size - from.0) / direction.0
+    } else {
+        ::std::f64::INFINITY
+    };
+    let mut next_y = if direction.1 > 0f64 {
+        ((tile_y + 1) as f64 * tile_size - from.1) / direction.1
+    } else if direction.1 < 0f64 {
+        (tile_y as f64 * tile_size - from.1) / direction.1
+    } else {
+        ::std::f64::INFINITY
+    };
+
+    let hit = |tile_x, tile_y, distance| RayHit {
+        tile_x : tile_x,
+        tile_y : tile_y,
+        x : from.0 + direction.0 * distance,
+        y : from.1 + direction.1 * distance,
+        distance : distance
+    };
+
+    while tile_x != end_x || tile_y != end_y {
+        let distance;
+        if next_x == next_y {
+            distance = next_x;
+            if distance > length {
+                break;
+            }
+            if blocks(tile_x + step_x, tile_y) {
+                return Some(hit(tile_x + step_x, tile_y, distance));
+            }
+            if blocks(tile_x, tile_y + step_y) {
+                return Some(hit(tile_x, tile_y + step_y, distance));
+            }
+            next_x += delta_x;
+            next_y += delta_y;
+            tile_x += step_x;
+            tile_y += step_y;
+        } else if next_x < next_y {
+            distance = next_x;
+            next_x += delta_x;
+            tile_x += step_x;
+        } else {
+            distance = next_y;
+            next_y += delta_y;
+            tile_y += step_y;
+        }
+
+        if distance > length {
+            break;
+        }
+
+        if blocks(tile_x, tile_y) {
+            return Some(hit(tile_x, tile_y, distance));
+        }
+    }
+    None
+}
+
+pub fn line_of_sight<F>(from : (f64, f64), to : (f64, f64), tile_size : f64, blocks : F) -> bool
+    where F : Fn(i32, i32) -> bool {
+    raycast(from, to, tile_size, blocks).is_none()
+}
+
+pub fn raycast_grid<F>(grid : &Grid<Cell>, from : (f64, f64), to : (f64, f64), tile_size : f64, blocks : F) -> Option<RayHit>
+    where F : Fn(&Cell) -> bool {
+    raycast(from, to, tile_size, |x, y| grid.get_if(x, y, |cell| blocks(cell)).is_some())
+}
+
+pub fn line_of_sight_grid<F>(grid : &Grid<Cell>, from : (f64, f64), to : (f64, f64), tile_size : f64, blocks : F) -> bool
+    where F : Fn(&Cell) -> bool {
+    raycast_grid(grid, from, to, tile_size, blocks).is_none()
+}
+
+impl TileMap {
+    // where a shot would hit a solid tile
+    pub fn raycast(&self, from : (f64, f64), to : (f64, f64), tile_size : f64) -> Option<RayHit> {
+        raycast(from, to, tile_size, |x, y| self.is_solid(x, y))
+    }
+
+    pub fn line_of_sight(&self, from : (f64, f64), to : (f64, f64), tile_size : f64) -> bool {
+        line_of_sight(from, to, tile_size, |x, y| self.blocks_sight(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a wall along x = 2 from y = 0 to 2, on a grid of 8 pixel tiles
+    fn wall(x : i32, y : i32) -> bool {
+        x == 2 && y >= 0 && y <= 2
+    }
+
+    #[test]
+    fn hits_the_first_blocking_tile() {
+        let hit = raycast((4f64, 12f64), (60f64, 12f64), 8f64, wall).unwrap();
+        assert_eq!((hit.tile_x, hit.tile_y), (2, 1));
+        assert_eq!((hit.x, hit.y, hit.distance), (16f64, 12f64, 12f64));
+        assert!(!line_of_sight((4f64, 12f64), (60f64, 12f64), 8f64, wall));
+    }
+
+    #[test]
+    fn misses_when_nothing_is_in_the_way() {
+        assert!(raycast((4f64, 12f64), (12f64, 44f64), 8f64, wall).is_none());
+        // stops short of the wall
+        assert!(line_of_sight((4f64, 12f64), (15f64, 12f64), 8f64, wall));
+    }
+
+    #[test]
+    fn does_not_slip_past_a_corner() {
+        // the ray passes exactly through the corner between (0, 0), (1, 0), (0, 1) and (1, 1)
+        let hit = raycast((4f64, 4f64), (20f64, 20f64), 8f64, |x, y| (x, y) == (1, 0)).unwrap();
+        assert_eq!((hit.tile_x, hit.tile_y), (1, 0));
+        assert!((hit.x - 8f64).abs() < 1e-9 && (hit.y - 8f64).abs() < 1e-9);
+        let hit = raycast((4f64, 4f64), (20f64, 20f64), 8f64, |x, y| (x, y) == (0, 1)).unwrap();
+        assert_eq!((hit.tile_x, hit.tile_y), (0, 1));
+        assert!(line_of_sight((4f64, 4f64), (20f64, 20f64), 8f64, |_, _| false));
+    }
+
+    #[test]
+    fn a_ray_without_length_sees_everything() {
+        assert!(raycast((4f64, 4f64), (4f64, 4f64), 8f64, |_, _| true).is_none());
+        assert!(line_of_sight((4f64, 4f64), (4f64, 4f64), 8f64, |_, _| true));
+    }
+}
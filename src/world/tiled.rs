@@ -0,0 +1,543 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use rustc_serialize::base64::FromBase64;
+use rustc_serialize::json::Json;
+
+use levelgenerator::Level;
+use motor::xml;
+use world::{Cell, SpawnPoint, Tile, TILES, tile_index};
+use world::tilemap::{TileMap, MAX_MAP_CELLS};
+
+// http://doc.mapeditor.org/reference/tmx-map-format/
+
+const TILE_SIZE : u32 = 8;
+const FIRST_GID : u32 = 1;
+const FLIP_FLAGS : u32 = 0xe0000000;
+
+const PROPERTY_TILE : &'static str = "tile";
+const PROPERTY_COLLISION : &'static str = "collision";
+const PROPERTY_ABOVE_ACTORS : &'static str = "above_actors";
+const PROPERTY_SEED : &'static str = "seed";
+const PROPERTY_AMBIENT_LIGHT : &'static str = "ambient_light";
+
+const SPAWN_LAYER : &'static str = "spawns";
+const PLAYER_SPAWN : &'static str = "player";
+
+// the parts of a Tiled map we care about, shared by the tmx and json readers
+struct MapData {
+    width : u32,
+    height : u32,
+    tile_width : u32,
+    tile_height : u32,
+    properties : HashMap<String, String>,
+    tiles : HashMap<u32, Tile>,
+    layers : Vec<LayerData>,
+    spawn_points : Vec<SpawnPoint>
+}
+
+struct LayerData {
+    name : String,
+    properties : HashMap<String, String>,
+    gids : Vec<u32>
+}
+
+impl MapData {
+    fn new() -> MapData {
+        MapData {
+            width : 0,
+            height : 0,
+            tile_width : TILE_SIZE,
+            tile_height : TILE_SIZE,
+            properties : HashMap::new(),
+            tiles : HashMap::new(),
+            layers : Vec::new(),
+            spawn_points : Vec::new()
+        }
+    }
+
+    // tilesets without a "tile" property use the order of world::TILES
+    fn add_tileset(&mut self, first_gid : u32, tile_count : u32, named_tiles : Vec<(u32, String)>) -> Result<(), String> {
+        for id in 0..tile_count {
+            if (id as usize) < TILES.len() {
+                self.tiles.insert(first_gid + id, TILES[id as usize]);
+            }
+        }
+        for (id, name) in named_tiles {
+            match Tile::from_name(&name) {
+                Some(tile) => { self.tiles.insert(first_gid + id, tile); },
+                None => return Err(format!("unknown tile '{}'", name))
+            }
+        }
+        Ok(())
+    }
+
+    fn into_level(self) -> Result<Level, String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("the map is empty"));
+        }
+        let cell_count = match self.width.checked_mul(self.height) {
+            Some(cell_count) if cell_count <= MAX_MAP_CELLS => cell_count,
+            _ => return Err(format!("the map is too large, {}x{} tiles", self.width, self.height))
+        };
+        let mut tile_map = TileMap::new(self.width, self.height);
+
+        for (index, layer_data) in self.layers.iter().enumerate() {
+            if layer_data.gids.len() != cell_count as usize {
+                return Err(format!("layer '{}' has {} tiles, expected {}", layer_data.name, layer_data.gids.len(), cell_count));
+            }
+            let layer = tile_map.add_layer(&layer_data.name,
+                                           get_bool(&layer_data.properties, PROPERTY_COLLISION),
+                                           get_bool(&layer_data.properties, PROPERTY_ABOVE_ACTORS),
+                                           index as i32);
+            for (i, gid) in layer_data.gids.iter().enumerate() {
+                let gid = gid & !FLIP_FLAGS;
+                if gid == 0 {
+                    continue;
+                }
+                match self.tiles.get(&gid) {
+                    Some(tile) => {
+                        let x = (i as u32 % self.width) as i32;
+                        let y = (i as u32 / self.width) as i32;
+                        layer.grid.set(x, y, Cell::new(*tile));
+                    },
+                    None => return Err(format!("layer '{}' uses unknown tile {}", layer_data.name, gid))
+                }
+            }
+        }
+
+        let start = self.spawn_points.iter().find(|s| s.kind == PLAYER_SPAWN)
+            .map_or((0, 0), |s| (tile_index(s.x, self.tile_width as f64), tile_index(s.y, self.tile_height as f64)));
+        tile_map.seed = self.properties.get(PROPERTY_SEED).and_then(|s| s.parse::<u32>().ok()).unwrap_or(0);
+        match self.properties.get(PROPERTY_AMBIENT_LIGHT).and_then(|c| parse_color(c)) {
+            Some(color) => tile_map.ambient_light = color,
+            None => {}
+        }
+        tile_map.spawn_points = self.spawn_points;
+
+        Ok(Level {
+            tile_map : tile_map,
+            start_tile : start
+        })
+    }
+}
+
+fn get_bool(properties : &HashMap<String, String>, name : &str) -> bool {
+    properties.get(name).map_or(false, |value| value == "true")
+}
+
+fn parse_u32(value : Option<&str>, name : &str) -> Result<u32, String> {
+    match value {
+        Some(value) => value.trim().parse::<u32>().map_err(|_| format!("'{}' is not a valid {}", value, name)),
+        None => Err(format!("missing {}", name))
+    }
+}
+
+fn parse_f64(value : Option<&str>, name : &str) -> Result<f64, String> {
+    match value {
+        Some(value) => value.trim().parse::<f64>().map_err(|_| format!("'{}' is not a valid {}", value, name)),
+        None => Ok(0f64)
+    }
+}
+
+// "#rrggbb", or "#aarrggbb" as written by Tiled
+fn parse_color(value : &str) -> Option<(u8, u8, u8)> {
+    let hex = value.trim_left_matches('#');
+    let rgb = match hex.len() {
+        6 => hex,
+        8 => &hex[2..],
+        _ => return None
+    };
+    u32::from_str_radix(rgb, 16).ok().map(|c| ((c >> 16) as u8, (c >> 8) as u8, c as u8))
+}
+
+fn format_color(color : (u8, u8, u8)) -> String {
+    format!("#ff{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+// uncompressed base64 data is a little endian u32 per tile, compressed data needs zlib which we don't have
+fn decode_base64(data : &str, compression : Option<&str>) -> Result<Vec<u32>, String> {
+    match compression {
+        Some(compression) if !compression.is_empty() => return Err(format!("unsupported layer compression '{}'", compression)),
+        _ => {}
+    }
+    let bytes = try!(data.trim().from_base64().map_err(|e| format!("invalid layer data: {}", e)));
+    if bytes.len() % 4 != 0 {
+        return Err(format!("layer data has {} bytes, expected a multiple of 4", bytes.len()));
+    }
+    Ok(bytes.chunks(4).map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24).collect())
+}
+
+fn get_gid(tile : &Tile) -> u32 {
+    FIRST_GID + TILES.iter().position(|t| t == tile).unwrap() as u32
+}
+
+fn get_gids(tile_map : &TileMap, layer_index : usize) -> Vec<u32> {
+    let grid = &tile_map.layers()[layer_index].grid;
+    let mut gids = Vec::new();
+    for y in 0..grid.height as i32 {
+        for x in 0..grid.width as i32 {
+            gids.push(grid.get(x, y).map_or(0, |cell| get_gid(&cell.tile)));
+        }
+    }
+    gids
+}
+
+pub fn load_level(path : &Path) -> Result<Level, String> {
+    let mut source = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut source)).map_err(|e| format!("{}: {}", path.display(), e)));
+
+    let map_data = match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => try!(read_tmx(&source)),
+        Some("json") => try!(read_json(&source)),
+        _ => return Err(format!("{}: unknown map format", path.display()))
+    };
+    map_data.into_level()
+}
+
+pub fn save_tile_map(tile_map : &TileMap, path : &Path) -> Result<(), String> {
+    let output = match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => try!(write_tmx(tile_map)),
+        Some("json") => write_json(tile_map),
+        _ => return Err(format!("{}: unknown map format", path.display()))
+    };
+    File::create(path).and_then(|mut f| f.write_all(output.as_bytes())).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn read_tmx_properties(element : &xml::Element) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    match element.get_child("properties") {
+        Some(p) => {
+            for property in p.get_children("property") {
+                match (property.get_attribute("name"), property.get_attribute("value")) {
+                    (Some(name), Some(value)) => { properties.insert(name.to_string(), value.to_string()); },
+                    _ => {}
+                }
+            }
+        },
+        None => {}
+    }
+    properties
+}
+
+fn read_tmx(source : &str) -> Result<MapData, String> {
+    let map = try!(xml::parse(source));
+    if map.name != "map" {
+        return Err(format!("expected <map> but found <{}>", map.name));
+    }
+
+    let mut map_data = MapData::new();
+    map_data.width = try!(parse_u32(map.get_attribute("width"), "width"));
+    map_data.height = try!(parse_u32(map.get_attribute("height"), "height"));
+    map_data.tile_width = try!(parse_u32(map.get_attribute("tilewidth"), "tilewidth"));
+    map_data.tile_height = try!(parse_u32(map.get_attribute("tileheight"), "tileheight"));
+    map_data.properties = read_tmx_properties(&map);
+
+    for element in map.children.iter() {
+        match &element.name[..] {
+            "tileset" => {
+                let first_gid = try!(parse_u32(element.get_attribute("firstgid"), "firstgid"));
+                let tile_count = element.get_attribute("tilecount").and_then(|c| c.parse::<u32>().ok()).unwrap_or(0);
+                let mut named_tiles = Vec::new();
+                for tile in element.get_children("tile") {
+                    let id = try!(parse_u32(tile.get_attribute("id"), "tile id"));
+                    match read_tmx_properties(tile).get(PROPERTY_TILE) {
+                        Some(name) => named_tiles.push((id, name.clone())),
+                        None => {}
+                    }
+                }
+                try!(map_data.add_tileset(first_gid, tile_count, named_tiles));
+            },
+            "layer" => {
+                let data = try!(element.get_child("data").ok_or(format!("layer without data")));
+                let gids = match data.get_attribute("encoding") {
+                    Some("csv") => {
+                        let mut gids = Vec::new();
+                        for value in data.text.split(',') {
+                            gids.push(try!(parse_u32(Some(value), "tile")));
+                        }
+                        gids
+                    },
+                    Some("base64") => try!(decode_base64(&data.text, data.get_attribute("compression"))),
+                    Some(encoding) => return Err(format!("unsupported layer encoding '{}'", encoding)),
+                    None => {
+                        let mut gids = Vec::new();
+                        for tile in data.get_children("tile") {
+                            gids.push(tile.get_attribute("gid").and_then(|g| g.parse::<u32>().ok()).unwrap_or(0));
+                        }
+                        gids
+                    }
+                };
+                map_data.layers.push(LayerData {
+                    name : element.get_attribute("name").unwrap_or("").to_string(),
+                    properties : read_tmx_properties(element),
+                    gids : gids
+                });
+            },
+            "objectgroup" => {
+                for object in element.get_children("object") {
+                    map_data.spawn_points.push(SpawnPoint {
+                        name : object.get_attribute("name").unwrap_or("").to_string(),
+                        kind : object.get_attribute("type").unwrap_or("").to_string(),
+                        x : try!(parse_f64(object.get_attribute("x"), "x")),
+                        y : try!(parse_f64(object.get_attribute("y"), "y"))
+                    });
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(map_data)
+}
+
+fn write_tmx_properties(output : &mut String, indent : &str, properties : &Vec<(&str, String)>) {
+    output.push_str(&format!("{}<properties>\n", indent));
+    for &(ref name, ref value) in properties.iter() {
+        output.push_str(&format!("{} <property name=\"{}\" value=\"{}\"/>\n", indent, xml::escape(name), xml::escape(value)));
+    }
+    output.push_str(&format!("{}</properties>\n", indent));
+}
+
+fn write_tmx(tile_map : &TileMap) -> Result<String, String> {
+    if tile_map.width == 0 || tile_map.height == 0 {
+        return Err(format!("can't write an empty map"));
+    }
+    let mut output = String::new();
+
+    output.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str(&format!("<map version=\"1.0\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\">\n",
+        tile_map.width, tile_map.height, TILE_SIZE, TILE_SIZE));
+    write_tmx_properties(&mut output, " ", &vec![(PROPERTY_SEED, tile_map.seed.to_string()),
+                                                  (PROPERTY_AMBIENT_LIGHT, format_color(tile_map.ambient_light))]);
+
+    output.push_str(&format!(" <tileset firstgid=\"{}\" name=\"tiles\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\">\n",
+        FIRST_GID, TILE_SIZE, TILE_SIZE, TILES.len()));
+    for (id, tile) in TILES.iter().enumerate() {
+        output.push_str(&format!("  <tile id=\"{}\">\n", id));
+        write_tmx_properties(&mut output, "   ", &vec![(PROPERTY_TILE, tile.name().to_string())]);
+        output.push_str("  </tile>\n");
+    }
+    output.push_str(" </tileset>\n");
+
+    for (index, layer) in tile_map.layers().iter().enumerate() {
+        output.push_str(&format!(" <layer name=\"{}\" width=\"{}\" height=\"{}\">\n", xml::escape(&layer.name), tile_map.width, tile_map.height));
+        write_tmx_properties(&mut output, "  ", &vec![(PROPERTY_COLLISION, layer.collision.to_string()),
+                                                       (PROPERTY_ABOVE_ACTORS, layer.above_actors.to_string())]);
+        output.push_str("  <data encoding=\"csv\">\n");
+        let gids = get_gids(tile_map, index);
+        // no trailing comma after the last tile
+        let rows = gids.chunks(tile_map.width as usize)
+            .map(|row| row.iter().map(|gid| gid.to_string()).collect::<Vec<String>>().join(","))
+            .collect::<Vec<String>>();
+        output.push_str(&rows.join(",\n"));
+        output.push_str("\n  </data>\n");
+        output.push_str(" </layer>\n");
+    }
+
+    output.push_str(&format!(" <objectgroup name=\"{}\">\n", SPAWN_LAYER));
+    for (id, spawn_point) in tile_map.spawn_points.iter().enumerate() {
+        output.push_str(&format!("  <object id=\"{}\" name=\"{}\" type=\"{}\" x=\"{}\" y=\"{}\"/>\n",
+            id + 1, xml::escape(&spawn_point.name), xml::escape(&spawn_point.kind), spawn_point.x, spawn_point.y));
+    }
+    output.push_str(" </objectgroup>\n");
+    output.push_str("</map>\n");
+    Ok(output)
+}
+
+fn json_to_string(json : &Json) -> String {
+    match *json {
+        Json::String(ref s) => s.clone(),
+        ref other => other.to_string()
+    }
+}
+
+// handles both the old object style and the newer array style of properties
+fn read_json_properties(json : &Json) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    match json.find("properties") {
+        Some(&Json::Object(ref object)) => {
+            for (name, value) in object.iter() {
+                properties.insert(name.clone(), json_to_string(value));
+            }
+        },
+        Some(&Json::Array(ref array)) => {
+            for property in array.iter() {
+                match (property.find("name").and_then(|n| n.as_string()), property.find("value")) {
+                    (Some(name), Some(value)) => { properties.insert(name.to_string(), json_to_string(value)); },
+                    _ => {}
+                }
+            }
+        },
+        _ => {}
+    }
+    properties
+}
+
+fn get_json_u32(json : &Json, name : &str) -> Result<u32, String> {
+    json.find(name).and_then(|v| v.as_u64()).map(|v| v as u32).ok_or(format!("missing {}", name))
+}
+
+fn read_json(source : &str) -> Result<MapData, String> {
+    let map = try!(Json::from_str(source).map_err(|e| format!("{}", e)));
+
+    let mut map_data = MapData::new();
+    map_data.width = try!(get_json_u32(&map, "width"));
+    map_data.height = try!(get_json_u32(&map, "height"));
+    map_data.tile_width = try!(get_json_u32(&map, "tilewidth"));
+    map_data.tile_height = try!(get_json_u32(&map, "tileheight"));
+    map_data.properties = read_json_properties(&map);
+
+    for tileset in map.find("tilesets").and_then(|t| t.as_array()).unwrap_or(&Vec::new()) {
+        let first_gid = try!(get_json_u32(tileset, "firstgid"));
+        let tile_count = get_json_u32(tileset, "tilecount").unwrap_or(0);
+        let mut named_tiles = Vec::new();
+        match tileset.find("tiles") {
+            Some(&Json::Array(ref tiles)) => {
+                for tile in tiles.iter() {
+                    let id = try!(get_json_u32(tile, "id"));
+                    match read_json_properties(tile).get(PROPERTY_TILE) {
+                        Some(name) => named_tiles.push((id, name.clone())),
+                        None => {}
+                    }
+                }
+            },
+            _ => {}
+        }
+        match tileset.find("tileproperties").and_then(|t| t.as_object()) {
+            Some(tile_properties) => {
+                for (id, properties) in tile_properties.iter() {
+                    let id = try!(parse_u32(Some(id), "tile id"));
+                    match properties.find(PROPERTY_TILE).and_then(|n| n.as_string()) {
+                        Some(name) => named_tiles.push((id, name.to_string())),
+                        None => {}
+                    }
+                }
+            },
+            None => {}
+        }
+        try!(map_data.add_tileset(first_gid, tile_count, named_tiles));
+    }
+
+    for layer in map.find("layers").and_then(|l| l.as_array()).unwrap_or(&Vec::new()) {
+        let name = layer.find("name").and_then(|n| n.as_string()).unwrap_or("").to_string();
+        match layer.find("type").and_then(|t| t.as_string()) {
+            Some("tilelayer") => {
+                // csv layers store an array of gids, base64 layers a string
+                let gids = match layer.find("data") {
+                    Some(&Json::Array(ref data)) => data.iter().map(|gid| gid.as_u64().unwrap_or(0) as u32).collect(),
+                    Some(&Json::String(ref data)) => {
+                        match layer.find("encoding").and_then(|e| e.as_string()) {
+                            Some("base64") => try!(decode_base64(data, layer.find("compression").and_then(|c| c.as_string()))),
+                            encoding => return Err(format!("unsupported layer encoding '{}'", encoding.unwrap_or("")))
+                        }
+                    },
+                    _ => return Err(format!("layer '{}' has no data", name))
+                };
+                map_data.layers.push(LayerData {
+                    name : name,
+                    properties : read_json_properties(layer),
+                    gids : gids
+                });
+            },
+            Some("objectgroup") => {
+                for object in layer.find("objects").and_then(|o| o.as_array()).unwrap_or(&Vec::new()) {
+                    map_data.spawn_points.push(SpawnPoint {
+                        name : object.find("name").and_then(|n| n.as_string()).unwrap_or("").to_string(),
+                        kind : object.find("type").and_then(|t| t.as_string()).unwrap_or("").to_string(),
+                        x : object.find("x").and_then(|x| x.as_f64()).unwrap_or(0f64),
+                        y : object.find("y").and_then(|y| y.as_f64()).unwrap_or(0f64)
+                    });
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(map_data)
+}
+
+fn json_property(name : &str, value : Json) -> Json {
+    let kind = match value {
+        Json::Boolean(_) => "bool",
+        Json::U64(_) | Json::I64(_) => "int",
+        Json::F64(_) => "float",
+        _ => "string"
+    };
+    let mut property = BTreeMap::new();
+    property.insert("name".to_string(), Json::String(name.to_string()));
+    property.insert("type".to_string(), Json::String(kind.to_string()));
+    property.insert("value".to_string(), value);
+    Json::Object(property)
+}
+
+fn write_json(tile_map : &TileMap) -> String {
+
+    let mut tiles = Vec::new();
+    for (id, tile) in TILES.iter().enumerate() {
+        let mut object = BTreeMap::new();
+        object.insert("id".to_string(), Json::U64(id as u64));
+        object.insert("properties".to_string(), Json::Array(vec![json_property(PROPERTY_TILE, Json::String(tile.name().to_string()))]));
+        tiles.push(Json::Object(object));
+    }
+
+    let mut tileset = BTreeMap::new();
+    tileset.insert("firstgid".to_string(), Json::U64(FIRST_GID as u64));
+    tileset.insert("name".to_string(), Json::String("tiles".to_string()));
+    tileset.insert("tilewidth".to_string(), Json::U64(TILE_SIZE as u64));
+    tileset.insert("tileheight".to_string(), Json::U64(TILE_SIZE as u64));
+    tileset.insert("tilecount".to_string(), Json::U64(TILES.len() as u64));
+    tileset.insert("tiles".to_string(), Json::Array(tiles));
+
+    let mut layers = Vec::new();
+    for (index, layer) in tile_map.layers().iter().enumerate() {
+        let mut object = BTreeMap::new();
+        object.insert("name".to_string(), Json::String(layer.name.clone()));
+        object.insert("type".to_string(), Json::String("tilelayer".to_string()));
+        object.insert("width".to_string(), Json::U64(tile_map.width as u64));
+        object.insert("height".to_string(), Json::U64(tile_map.height as u64));
+        object.insert("x".to_string(), Json::U64(0));
+        object.insert("y".to_string(), Json::U64(0));
+        object.insert("opacity".to_string(), Json::U64(1));
+        object.insert("visible".to_string(), Json::Boolean(true));
+        object.insert("properties".to_string(), Json::Array(vec![json_property(PROPERTY_COLLISION, Json::Boolean(layer.collision)),
+                                                                   json_property(PROPERTY_ABOVE_ACTORS, Json::Boolean(layer.above_actors))]));
+        object.insert("data".to_string(), Json::Array(get_gids(tile_map, index).iter().map(|gid| Json::U64(*gid as u64)).collect()));
+        layers.push(Json::Object(object));
+    }
+
+    let mut objects = Vec::new();
+    for (id, spawn_point) in tile_map.spawn_points.iter().enumerate() {
+        let mut object = BTreeMap::new();
+        object.insert("id".to_string(), Json::U64(id as u64 + 1));
+        object.insert("name".to_string(), Json::String(spawn_point.name.clone()));
+        object.insert("type".to_string(), Json::String(spawn_point.kind.clone()));
+        object.insert("x".to_string(), Json::F64(spawn_point.x));
+        object.insert("y".to_string(), Json::F64(spawn_point.y));
+        object.insert("width".to_string(), Json::U64(0));
+        object.insert("height".to_string(), Json::U64(0));
+        object.insert("rotation".to_string(), Json::U64(0));
+        object.insert("visible".to_string(), Json::Boolean(true));
+        objects.push(Json::Object(object));
+    }
+    let mut object_group = BTreeMap::new();
+    object_group.insert("name".to_string(), Json::String(SPAWN_LAYER.to_string()));
+    object_group.insert("type".to_string(), Json::String("objectgroup".to_string()));
+    object_group.insert("objects".to_string(), Json::Array(objects));
+    layers.push(Json::Object(object_group));
+
+    let mut map = BTreeMap::new();
+    map.insert("version".to_string(), Json::U64(1));
+    map.insert("orientation".to_string(), Json::String("orthogonal".to_string()));
+    map.insert("renderorder".to_string(), Json::String("right-down".to_string()));
+    map.insert("width".to_string(), Json::U64(tile_map.width as u64));
+    map.insert("height".to_string(), Json::U64(tile_map.height as u64));
+    map.insert("tilewidth".to_string(), Json::U64(TILE_SIZE as u64));
+    map.insert("tileheight".to_string(), Json::U64(TILE_SIZE as u64));
+    map.insert("properties".to_string(), Json::Array(vec![json_property(PROPERTY_SEED, Json::U64(tile_map.seed as u64)),
+                                                          json_property(PROPERTY_AMBIENT_LIGHT, Json::String(format_color(tile_map.ambient_light)))]));
+    map.insert("tilesets".to_string(), Json::Array(vec![Json::Object(tileset)]));
+    map.insert("layers".to_string(), Json::Array(layers));
+
+    format!("{}\n", Json::Object(map).pretty())
+}
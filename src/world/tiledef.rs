@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use world::Tile;
+
+const DEFAULT_FRICTION : f64 = 0.87f64;
+const WALL_HIT_POINTS : u32 = 5;
+
+#[derive(Clone, Debug)]
+pub struct TileDef {
+    pub solid : bool,
+    pub blocks_sight : bool,
+    pub liquid : bool,
+    pub friction : f64,
+    pub damage_per_second : f64,
+    // destructible tiles have hit points, the rest can't be destroyed
+    pub hit_points : Option<u32>,
+    // color and radius of the light the tile emits
+    pub light : Option<((u8, u8, u8), f64)>
+}
+
+impl TileDef {
+    pub fn new() -> TileDef {
+        TileDef {
+            solid : false,
+            blocks_sight : false,
+            liquid : false,
+            friction : DEFAULT_FRICTION,
+            damage_per_second : 0f64,
+            hit_points : None,
+            light : None
+        }
+    }
+
+    pub fn solid(mut self, solid : bool) -> TileDef {
+        self.solid = solid;
+        self
+    }
+
+    pub fn blocks_sight(mut self, blocks_sight : bool) -> TileDef {
+        self.blocks_sight = blocks_sight;
+        self
+    }
+
+    pub fn liquid(mut self, liquid : bool) -> TileDef {
+        self.liquid = liquid;
+        self
+    }
+
+    pub fn friction(mut self, friction : f64) -> TileDef {
+        self.friction = friction;
+        self
+    }
+
+    pub fn damage_per_second(mut self, damage_per_second : f64) -> TileDef {
+        self.damage_per_second = damage_per_second;
+        self
+    }
+
+    pub fn destructible(mut self, hit_points : u32) -> TileDef {
+        self.hit_points = Some(hit_points);
+        self
+    }
+
+    pub fn light(mut self, color : (u8, u8, u8), radius : f64) -> TileDef {
+        self.light = Some((color, radius));
+        self
+    }
+}
+
+pub struct TileRegistry {
+    defs : HashMap<Tile, TileDef>,
+    fallback : TileDef
+}
+
+impl TileRegistry {
+    pub fn new() -> TileRegistry {
+        TileRegistry {
+            defs : HashMap::new(),
+            fallback : TileDef::new()
+        }
+    }
+
+    pub fn with_defaults() -> TileRegistry {
+        let mut registry = TileRegistry::new();
+        registry.add(Tile::Grass, TileDef::new());
+        registry.add(Tile::Floor, TileDef::new());
+        registry.add(Tile::Water, TileDef::new().liquid(true).friction(0.8f64));
+        registry.add(Tile::Solid, TileDef::new().solid(true).blocks_sight(true));
+        // the front of a wall can be shot through, solid rock can't
+        registry.add(Tile::Wall, TileDef::new().solid(true).blocks_sight(true).destructible(WALL_HIT_POINTS));
+        // the generator only makes floor and walls, water, lava and ice come from loaded Tiled maps
+        registry.add(Tile::Lava, TileDef::new().liquid(true).friction(0.8f64).damage_per_second(25f64).light((255, 120, 40), 24f64));
+        registry.add(Tile::Ice, TileDef::new().friction(0.98f64));
+        registry
+    }
+
+    pub fn add(&mut self, tile : Tile, def : TileDef) {
+        self.defs.insert(tile, def);
+    }
+
+    // tiles without a definition behave like plain floor
+    pub fn get(&self, tile : &Tile) -> &TileDef {
+        self.defs.get(tile).unwrap_or(&self.fallback)
+    }
+
+    pub fn get_fallback(&self) -> &TileDef {
+        &self.fallback
+    }
+}
@@ -0,0 +1,320 @@
+use world::{Cell, SpawnPoint, tile_legend};
+use world::grid::{self, Grid};
+use world::tiledef::{TileDef, TileRegistry};
+use std::io::{self, Read, Write};
+use std::mem;
+
+pub const LAYER_GROUND : &'static str = "ground";
+pub const LAYER_WALLS : &'static str = "walls";
+pub const LAYER_DECORATION : &'static str = "decoration";
+pub const LAYER_OVERLAY : &'static str = "overlay";
+
+const DEFAULT_AMBIENT_LIGHT : (u8, u8, u8) = (48, 48, 64);
+
+// loaded maps above this many cells are treated as broken, the fov and light maps are this size too
+pub const MAX_MAP_CELLS : u32 = 1 << 24;
+
+const SNAPSHOT_MAGIC : &'static [u8; 4] = b"TMS1";
+const LAYER_COLLISION : u8 = 1;
+const LAYER_ABOVE_ACTORS : u8 = 2;
+
+pub struct Layer {
+    pub name : String,
+    pub grid : Grid<Cell>,
+    pub collision : bool,
+    pub above_actors : bool,
+    pub render_order : i32
+}
+
+impl Layer {
+    pub fn new(name : &str, width : u32, height : u32) -> Layer {
+        Layer {
+            name : name.to_string(),
+            grid : Grid::new(width, height),
+            collision : false,
+            above_actors : false,
+            render_order : 0
+        }
+    }
+}
+
+// the cells that changed since the changes were last taken
+pub struct TileChanges {
+    pub all : bool,
+    pub cells : Vec<(i32, i32)>
+}
+
+impl TileChanges {
+    pub fn new() -> TileChanges {
+        TileChanges {
+            all : false,
+            cells : Vec::new()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.cells.is_empty()
+    }
+}
+
+pub struct TileMap {
+    pub width : u32,
+    pub height : u32,
+    pub tile_defs : TileRegistry,
+    pub spawn_points : Vec<SpawnPoint>,
+    // seed for picking tile variants and decorations
+    pub seed : u32,
+    pub ambient_light : (u8, u8, u8),
+    layers : Vec<Layer>,
+    changes : TileChanges
+}
+
+impl TileMap {
+    pub fn new(width : u32, height : u32) -> TileMap {
+        TileMap {
+            width : width,
+            height : height,
+            tile_defs : TileRegistry::with_defaults(),
+            spawn_points : Vec::new(),
+            seed : 0,
+            ambient_light : DEFAULT_AMBIENT_LIGHT,
+            layers : Vec::new(),
+            changes : TileChanges { all : true, cells : Vec::new() }
+        }
+    }
+
+    // the standard layers used by generated levels
+    pub fn with_default_layers(width : u32, height : u32) -> TileMap {
+        let mut tile_map = TileMap::new(width, height);
+        tile_map.add_layer(LAYER_GROUND, false, false, 0);
+        tile_map.add_layer(LAYER_WALLS, true, false, 1);
+        tile_map.add_layer(LAYER_DECORATION, false, false, 2);
+        tile_map.add_layer(LAYER_OVERLAY, false, true, 3);
+        tile_map
+    }
+
+    pub fn add_layer(&mut self, name : &str, collision : bool, above_actors : bool, render_order : i32) -> &mut Layer {
+        let mut layer = Layer::new(name, self.width, self.height);
+        layer.collision = collision;
+        layer.above_actors = above_actors;
+        layer.render_order = render_order;
+
+        // keep the layers sorted in render order
+        let index = self.layers.iter().position(|l| l.render_order > render_order).unwrap_or(self.layers.len());
+        self.layers.insert(index, layer);
+        self.changes.all = true;
+        &mut self.layers[index]
+    }
+
+    pub fn get_layer(&self, name : &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    // handing out a layer counts as changing all of it
+    pub fn get_layer_mut(&mut self, name : &str) -> Option<&mut Layer> {
+        self.changes.all = true;
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Layer] {
+        self.changes.all = true;
+        &mut self.layers
+    }
+
+    pub fn set(&mut self, layer_name : &str, x : i32, y : i32, cell : Cell) {
+        match self.layers.iter_mut().find(|l| l.name == layer_name) {
+            Some(layer) => layer.grid.set(x, y, cell),
+            None => panic!("no layer named {}", layer_name)
+        }
+        self.changes.cells.push((x, y));
+    }
+
+    pub fn take_changes(&mut self) -> TileChanges {
+        mem::replace(&mut self.changes, TileChanges::new())
+    }
+
+    pub fn is_solid(&self, x : i32, y : i32) -> bool {
+        let tile_defs = &self.tile_defs;
+        self.layers.iter().filter(|l| l.collision).any(|l| {
+            l.grid.get_if(x, y, |cell| tile_defs.get(&cell.tile).solid).is_some()
+        })
+    }
+
+    pub fn blocks_sight(&self, x : i32, y : i32) -> bool {
+        let tile_defs = &self.tile_defs;
+        self.layers.iter().filter(|l| !l.above_actors).any(|l| {
+            l.grid.get_if(x, y, |cell| tile_defs.get(&cell.tile).blocks_sight).is_some()
+        })
+    }
+
+    // the definition of the topmost tile an actor standing on x, y touches
+    pub fn get_tile_def(&self, x : i32, y : i32) -> &TileDef {
+        let cell = self.layers.iter().rev()
+            .filter(|l| !l.above_actors)
+            .filter_map(|l| l.grid.get(x, y))
+            .next();
+        match cell {
+            Some(cell) => self.tile_defs.get(&cell.tile),
+            None => self.tile_defs.get_fallback()
+        }
+    }
+
+    pub fn get_tile_def_at_position(&self, x : f64, y : f64, tile_size : f64) -> &TileDef {
+        self.get_tile_def((x / tile_size).floor() as i32, (y / tile_size).floor() as i32)
+    }
+
+    // damages a destructible tile in a collision layer, returns true if it was destroyed
+    pub fn damage(&mut self, x : i32, y : i32, amount : u32) -> bool {
+        let tile_defs = &self.tile_defs;
+        for layer in self.layers.iter_mut().filter(|l| l.collision) {
+            let mut destroyed = false;
+            match layer.grid.get_mut(x, y) {
+                Some(cell) => {
+                    match tile_defs.get(&cell.tile).hit_points {
+                        Some(max_hit_points) => {
+                            let hit_points = cell.hit_points.unwrap_or(max_hit_points);
+                            if hit_points > amount {
+                                cell.hit_points = Some(hit_points - amount);
+                            } else {
+                                destroyed = true;
+                            }
+                        },
+                        None => {}
+                    }
+                },
+                None => {}
+            }
+            if destroyed {
+                layer.grid.remove(x, y);
+                self.changes.cells.push((x, y));
+                return true;
+            }
+        }
+        false
+    }
+
+    // ascii dump of every layer, for debugging
+    pub fn to_ascii(&self) -> Result<String, String> {
+        let legend = tile_legend();
+        let mut output = String::new();
+        for layer in self.layers.iter() {
+            output.push_str(&format!("[{}]\n", layer.name));
+            output.push_str(&try!(layer.grid.to_ascii(&legend).map_err(|e| format!("layer {}: {}", layer.name, e))));
+        }
+        Ok(output)
+    }
+
+    // the tiles of every layer in the binary grid format. variants and decorations are picked
+    // again from the seed, spawn points and hit points aren't kept
+    pub fn write_snapshot<W : Write>(&self, writer : &mut W) -> io::Result<()> {
+        let legend = tile_legend();
+        if self.layers.len() > 255 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many layers"));
+        }
+        try!(writer.write_all(SNAPSHOT_MAGIC));
+        try!(writer.write_all(&grid::u32_to_bytes(self.width)));
+        try!(writer.write_all(&grid::u32_to_bytes(self.height)));
+        try!(writer.write_all(&grid::u32_to_bytes(self.seed)));
+        try!(writer.write_all(&[self.layers.len() as u8]));
+        for layer in self.layers.iter() {
+            let name = layer.name.as_bytes();
+            if name.len() > 255 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("layer name {} is too long", layer.name)));
+            }
+            let mut flags = 0;
+            if layer.collision {
+                flags |= LAYER_COLLISION;
+            }
+            if layer.above_actors {
+                flags |= LAYER_ABOVE_ACTORS;
+            }
+            try!(writer.write_all(&[name.len() as u8]));
+            try!(writer.write_all(name));
+            try!(writer.write_all(&[flags]));
+            try!(writer.write_all(&grid::u32_to_bytes(layer.render_order as u32)));
+            try!(layer.grid.write_binary(writer, &legend));
+        }
+        Ok(())
+    }
+
+    pub fn read_snapshot<R : Read>(reader : &mut R) -> io::Result<TileMap> {
+        let legend = tile_legend();
+        let mut header = [0u8; 17];
+        try!(grid::read_exact(reader, &mut header));
+        if &header[0..4] != &SNAPSHOT_MAGIC[..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a tile map snapshot"));
+        }
+        let width = grid::bytes_to_u32(&header[4..8]);
+        let height = grid::bytes_to_u32(&header[8..12]);
+        if width == 0 || height == 0 || width as u64 * height as u64 > MAX_MAP_CELLS as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid map size {}x{}", width, height)));
+        }
+        if header[16] == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the snapshot has no layers"));
+        }
+        let mut tile_map = TileMap::new(width, height);
+        tile_map.seed = grid::bytes_to_u32(&header[12..16]);
+
+        for _ in 0..header[16] {
+            let mut length = [0u8; 1];
+            try!(grid::read_exact(reader, &mut length));
+            let mut name = vec![0u8; length[0] as usize];
+            try!(grid::read_exact(reader, &mut name));
+            let name = try!(String::from_utf8(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid layer name")));
+            let mut properties = [0u8; 5];
+            try!(grid::read_exact(reader, &mut properties));
+
+            let layer_grid = try!(Grid::read_binary(reader, &legend));
+            if layer_grid.width != tile_map.width || layer_grid.height != tile_map.height {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("layer {} has the wrong size", name)));
+            }
+            let flags = properties[0];
+            let render_order = grid::bytes_to_u32(&properties[1..5]) as i32;
+            tile_map.add_layer(&name, flags & LAYER_COLLISION != 0, flags & LAYER_ABOVE_ACTORS != 0, render_order).grid = layer_grid;
+        }
+        Ok(tile_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use world::{Cell, Tile};
+    use world::grid;
+
+    fn header(width : u32, height : u32, layer_count : u8) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&grid::u32_to_bytes(width));
+        bytes.extend_from_slice(&grid::u32_to_bytes(height));
+        bytes.extend_from_slice(&grid::u32_to_bytes(1));
+        bytes.push(layer_count);
+        bytes
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut tile_map = TileMap::with_default_layers(3, 2);
+        tile_map.seed = 7;
+        tile_map.set(LAYER_GROUND, 1, 1, Cell::new(Tile::Floor));
+        tile_map.set(LAYER_WALLS, 2, 0, Cell::new(Tile::Wall));
+        let mut bytes = Vec::new();
+        tile_map.write_snapshot(&mut bytes).unwrap();
+
+        let read = TileMap::read_snapshot(&mut &bytes[..]).unwrap();
+        assert_eq!((read.width, read.height, read.seed), (3, 2, 7));
+        assert_eq!(read.layers().len(), 4);
+        assert_eq!(read.to_ascii().unwrap(), tile_map.to_ascii().unwrap());
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_headers() {
+        assert!(TileMap::read_snapshot(&mut &header(0, 10, 1)[..]).is_err());
+        assert!(TileMap::read_snapshot(&mut &header(10, 0, 1)[..]).is_err());
+        assert!(TileMap::read_snapshot(&mut &header(0xffffffff, 0xffffffff, 1)[..]).is_err());
+        assert!(TileMap::read_snapshot(&mut &header(10, 10, 0)[..]).is_err());
+    }
+}